@@ -1,11 +1,14 @@
-use crate::gfa::GFAGraph;
+use crate::gfa::{EdgeData, GFAGraph, GraphView, NodeData, NodeRole, Orientation};
+use crate::region::Region;
 use crate::vcf::BubbleVariant;
 use anyhow::Result;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use serde::Deserialize;
 use std::cmp::max;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 // query length of a list of nodes
-fn query_at_len(at: &Vec<Vec<u8>>, g: &GFAGraph) -> Result<usize> {
+pub(crate) fn query_at_len<G: GraphView + ?Sized>(at: &Vec<Vec<u8>>, g: &G) -> Result<usize> {
     let mut at_len = 0;
     for node in at {
         // let node_name = &node.to_string();
@@ -19,105 +22,963 @@ fn query_at_len(at: &Vec<Vec<u8>>, g: &GFAGraph) -> Result<usize> {
 }
 
 // return max length of all paths and max step of all paths
+fn query_dis_step<G: GraphView + ?Sized>(bubble: &BubbleVariant, g: &G) -> Result<(usize, usize)> {
+    let mut max_distance = 0;
+    let mut max_step = 0;
+    for allele in 0..bubble.allele_count() {
+        let nodes = bubble.get_allele_nodes(allele, false);
+        max_distance = max(max_distance, query_at_len(&nodes, g)?);
+        max_step = max(max_step, nodes.len());
+    }
+    Ok((max_distance, max_step))
+}
 
-fn query_dis_step(bubble: &BubbleVariant, g: &GFAGraph) -> Result<(usize, usize)> {
-    let ref_nodes = bubble.get_ref_nodes(false);
-    let alt_nodes = bubble.get_alt_nodes(false);
-    let ref_len = query_at_len(&ref_nodes, g)?;
-    let alt_len = query_at_len(&alt_nodes, g)?;
-    Ok((max(ref_len, alt_len), max(ref_nodes.len(), alt_nodes.len())))
+// how the flank and hop limits are joined when both are set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CombineMode {
+    // a neighbour within either limit is added
+    Any,
+    // a neighbour has to be within both limits, so a short hop count cannot pull in long nodes
+    #[default]
+    All,
 }
 
-pub fn extract_subgraph_by_bfs(bubble: &BubbleVariant, g: &GFAGraph) -> Result<GFAGraph> {
-    // get all start nodes, max distance and max step
+// which links the context follows from the bubble nodes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum WalkMode {
+    // every neighbour, whichever side of the node it is linked to
+    #[default]
+    Undirected,
+    // leave a node only through the side opposite the one it was entered on
+    Sided,
+    // the path of the reference sample from the bubble source to its sink and its flanks
+    // along the path
+    Reference,
+}
+
+// size of the neighbourhood extracted around a bubble
+#[derive(Debug, Clone, Default, PartialEq, Eq, clap::Args)]
+pub struct ExtractOptions {
+    /// Context in bp walked out from the bubble nodes
+    /// [default: longest allele, with --hops unset]
+    #[arg(long)]
+    pub flank: Option<usize>,
+    /// Context in links walked out from the bubble nodes
+    /// [default: most steps of an allele, with --flank unset]
+    #[arg(long)]
+    pub hops: Option<usize>,
+    /// Stop adding context nodes once the subgraph has this many, the furthest are dropped
+    #[arg(long)]
+    pub max_nodes: Option<usize>,
+    /// Stop adding context nodes once the subgraph has this many links
+    #[arg(long)]
+    pub max_edges: Option<usize>,
+    /// Stop adding context nodes once the subgraph has this many bp of sequence
+    #[arg(long)]
+    pub max_bp: Option<usize>,
+    /// Add a neighbour within both the flank and the hops (all), or within either (any)
+    #[arg(long, value_enum, default_value = "all")]
+    pub combine: CombineMode,
+    /// Follow every neighbour (undirected), enter and leave nodes on opposite sides (sided),
+    /// or follow the reference path from source to sink (reference)
+    #[arg(
+        long,
+        value_enum,
+        default_value = "undirected",
+        requires_if("reference", "reference")
+    )]
+    pub walk: WalkMode,
+    /// Sample or path (sample#haplotype, path name) followed by --walk reference
+    #[arg(long)]
+    pub reference: Option<String>,
+}
+
+impl ExtractOptions {
+    // limits sized by the bubble itself when neither flank nor hops is given
+    fn resolve<G: GraphView + ?Sized>(&self, bubble: &BubbleVariant, g: &G) -> Result<Self> {
+        if self.flank.is_some() || self.hops.is_some() {
+            return Ok(self.clone());
+        }
+        let (max_distance, max_step) = query_dis_step(bubble, g)?;
+        Ok(Self {
+            flank: Some(max_distance),
+            hops: Some(max_step),
+            ..self.clone()
+        })
+    }
+
+    // whether a node reached after distance bp and step links is added, an unset limit
+    // does not count
+    fn within(&self, distance: usize, step: usize) -> bool {
+        match (self.flank, self.hops) {
+            (Some(flank), Some(hops)) => match self.combine {
+                CombineMode::Any => distance <= flank || step <= hops,
+                CombineMode::All => distance <= flank && step <= hops,
+            },
+            (Some(flank), None) => distance <= flank,
+            (None, Some(hops)) => step <= hops,
+            (None, None) => false,
+        }
+    }
+}
 
+// role and status of every node on an allele traversal: the first and last REF nodes are the
+// ENDPOINT, other nodes on more than one allele are SHARED, ALT nodes get their allele name
+fn allele_roles(bubble: &BubbleVariant) -> Vec<(Vec<u8>, NodeRole, String)> {
+    let mut nodes: Vec<(Vec<u8>, Vec<usize>)> = Vec::new();
+    let mut positions: HashMap<Vec<u8>, usize> = HashMap::new();
+    for allele in 0..bubble.allele_count() {
+        for node in bubble.get_allele_nodes(allele, true) {
+            match positions.get(&node) {
+                Some(&i) if !nodes[i].1.contains(&allele) => nodes[i].1.push(allele),
+                Some(_) => {}
+                None => {
+                    positions.insert(node.clone(), nodes.len());
+                    nodes.push((node, vec![allele]));
+                }
+            }
+        }
+    }
     let ref_nodes = bubble.get_ref_nodes(true);
-    let alt_nodes = bubble.get_alt_nodes(true);
+    let boundary = [ref_nodes.first(), ref_nodes.last()];
+    nodes
+        .into_iter()
+        .map(|(node, alleles)| {
+            let role = if boundary.contains(&Some(&node)) {
+                NodeRole::Endpoint
+            } else if alleles.len() > 1 {
+                NodeRole::Shared
+            } else if alleles[0] == 0 {
+                NodeRole::Ref
+            } else {
+                NodeRole::Alt
+            };
+            let status = match role {
+                NodeRole::Alt => BubbleVariant::allele_name(alleles[0]),
+                _ => role.to_string(),
+            };
+            (node, role, status)
+        })
+        .collect()
+}
+
+// copy a node of the whole graph into the subgraph, with its role, status, samples and the
+// count of its neighbours left out
+fn copy_node<G: GraphView + ?Sized>(
+    subgraph: &mut GFAGraph,
+    g: &G,
+    node_idx: NodeIndex,
+    (role, status): (NodeRole, &str),
+    hidden: usize,
+) -> Result<()> {
+    if let Some(node_data) = g.get_node_data(node_idx) {
+        subgraph.add_node_data(NodeData {
+            status: status.to_string(),
+            role: Some(role),
+            samples: g.samples_through(node_idx),
+            hidden,
+            ..node_data
+        })?;
+    }
+    Ok(())
+}
 
-    let (max_distance, max_step) = query_dis_step(bubble, g)?;
+// copy a link of the whole graph into the subgraph, both nodes have to be there
+fn copy_edge<G: GraphView + ?Sized>(
+    subgraph: &mut GFAGraph,
+    g: &G,
+    from_idx: NodeIndex,
+    to_idx: NodeIndex,
+    edge_data: EdgeData,
+) -> Result<()> {
+    match (g.get_id(from_idx), g.get_id(to_idx)) {
+        (Some(from_id), Some(to_id)) => subgraph.add_edge(&from_id, &to_id, edge_data),
+        _ => Err(anyhow::anyhow!("Node data not found")),
+    }
+}
 
-    // init a subgraph
-    let mut subgraph = GFAGraph::new();
+// a seed node with its role and status, None for extra seeds that are only context
+type Seed = (NodeIndex, Option<(NodeRole, String)>);
 
-    let mut visited = HashSet::new();
-    let mut queue = VecDeque::new();
+// a node reached from the seeds, seeds are at distance and step 0
+struct Reached {
+    node_idx: NodeIndex,
+    // None for context nodes, FLANK or OFF_PATH once the subgraph is built
+    label: Option<(NodeRole, String)>,
+    distance: usize,
+    step: usize,
+}
+
+// nodes and links within the flank and hops, before the budgets
+#[derive(Default)]
+struct Reach {
+    nodes: Vec<Reached>,
+    // first seeds.len() of nodes
+    seeds: usize,
+    edges: Vec<(EdgeIndex, NodeIndex, NodeIndex, EdgeData)>,
+}
+
+impl Reach {
+    fn from_seeds(seeds: &[Seed]) -> (Self, HashSet<NodeIndex>) {
+        let mut reach = Self::default();
+        let mut added = HashSet::new();
+        for (node_idx, label) in seeds {
+            if added.insert(*node_idx) {
+                reach.push(*node_idx, label.clone(), 0, 0);
+            }
+        }
+        reach.seeds = reach.nodes.len();
+        (reach, added)
+    }
 
-    // add start nodes to queue
-    for node in ref_nodes {
-        if let Some(node_idx) = g.get_node_idx(&node) {
-            if !visited.contains(&node_idx) {
-                queue.push_back((node_idx, 0, 0));
-                visited.insert(node_idx);
-
-                // add node to subgraph
-                if let Some(node_data) = g.get_node_data(node_idx) {
-                    subgraph.add_node(
-                        node_data.id.clone(),
-                        node_data.sequence.clone(),
-                        "REF".to_string(),
-                    )?;
+    // links with both ends on seeds are kept whatever the flank and hops
+    fn link_seeds<G: GraphView + ?Sized>(&mut self, g: &G, visited_edges: &mut HashSet<EdgeIndex>) {
+        let seeds: Vec<NodeIndex> = self.nodes[..self.seeds]
+            .iter()
+            .map(|seed| seed.node_idx)
+            .collect();
+        for &node_idx in seeds.iter() {
+            for (edge_idx, from_idx, to_idx, edge_data) in g.edges(node_idx) {
+                if seeds.contains(&from_idx)
+                    && seeds.contains(&to_idx)
+                    && visited_edges.insert(edge_idx)
+                {
+                    self.edges.push((edge_idx, from_idx, to_idx, edge_data));
                 }
             }
         }
     }
 
-    // add start nodes to queue
-    for node in alt_nodes {
-        if let Some(node_idx) = g.get_node_idx(&node) {
-            if !visited.contains(&node_idx) {
-                queue.push_back((node_idx, 0, 0));
-                visited.insert(node_idx);
+    fn push(
+        &mut self,
+        node_idx: NodeIndex,
+        label: Option<(NodeRole, String)>,
+        distance: usize,
+        step: usize,
+    ) {
+        self.nodes.push(Reached {
+            node_idx,
+            label,
+            distance,
+            step,
+        });
+    }
+}
+
+pub fn extract_subgraph_by_bfs<G: GraphView + ?Sized>(
+    bubble: &BubbleVariant,
+    g: &G,
+) -> Result<GFAGraph> {
+    extract_subgraph_by_bfs_with(bubble, &[], &ExtractOptions::default(), g)
+}
 
-                // add node to subgraph
-                if let Some(node_data) = g.get_node_data(node_idx) {
-                    subgraph.add_node(
-                        node_data.id.clone(),
-                        node_data.sequence.clone(),
-                        "ALT".to_string(),
-                    )?;
+// same as extract_subgraph_by_bfs, with extra seed nodes e.g. from nested bubbles and the
+// size of the context
+pub fn extract_subgraph_by_bfs_with<G: GraphView + ?Sized>(
+    bubble: &BubbleVariant,
+    extra_nodes: &[String],
+    options: &ExtractOptions,
+    g: &G,
+) -> Result<GFAGraph> {
+    // get all start nodes of every allele, and the limits of the context
+    let options = options.resolve(bubble, g)?;
+
+    let roles = allele_roles(bubble);
+    let anchors: Vec<NodeIndex> = roles
+        .iter()
+        .filter(|(_, role, _)| *role == NodeRole::Endpoint)
+        .filter_map(|(node, _, _)| g.get_node_idx(node))
+        .collect();
+    let mut seeds: Vec<Seed> = roles
+        .into_iter()
+        .filter_map(|(node, role, status)| Some((g.get_node_idx(&node)?, Some((role, status)))))
+        .collect();
+    seeds.extend(
+        extra_nodes
+            .iter()
+            .filter_map(|node| Some((g.get_node_idx(node.as_bytes())?, None))),
+    );
+
+    let reach = match options.walk {
+        WalkMode::Undirected => reach_undirected(&seeds, &options, g),
+        WalkMode::Sided => reach_by_sides(&seeds, &options, g),
+        WalkMode::Reference if !g.paths_loaded() => {
+            return Err(anyhow::anyhow!(
+                "Reference walks need the paths, which could not be loaded"
+            ));
+        }
+        WalkMode::Reference => reach_along_path(bubble, &seeds, &options, g)?,
+    };
+    build_subgraph(reach, &anchors, &options, g)
+}
+
+// extract the subgraph around the nodes overlapping a region of stable coordinates, a whole
+// contig is refused as it would lay out an entire chromosome
+pub fn extract_subgraph_by_region<G: GraphView + ?Sized>(
+    region: &Region,
+    options: &ExtractOptions,
+    g: &G,
+) -> Result<GFAGraph> {
+    if region.is_whole_contig() {
+        return Err(anyhow::anyhow!(
+            "Region {} has no range, give one as {}:start-end",
+            region,
+            region.contig
+        ));
+    }
+    let region_nodes = g.nodes_in_region(region);
+    if region_nodes.is_empty() {
+        return Err(anyhow::anyhow!("No nodes found in region {}", region));
+    }
+
+    // the region itself sets the size of its neighbourhood unless flank or hops is given
+    let mut options = options.clone();
+    if options.flank.is_none() && options.hops.is_none() {
+        options.flank = Some(
+            region_nodes
+                .iter()
+                .filter_map(|&node_idx| g.get_seq_len(node_idx))
+                .sum(),
+        );
+        options.hops = Some(region_nodes.len());
+    }
+
+    let seeds: Vec<Seed> = region_nodes
+        .iter()
+        .map(|&node_idx| {
+            let label = (NodeRole::Region, NodeRole::Region.to_string());
+            (node_idx, Some(label))
+        })
+        .collect();
+
+    // a region has no source and sink to follow a reference path between, it is walked sided
+    let reach = match options.walk {
+        WalkMode::Undirected => reach_undirected(&seeds, &options, g),
+        WalkMode::Sided | WalkMode::Reference => reach_by_sides(&seeds, &options, g),
+    };
+    build_subgraph(reach, &region_nodes, &options, g)
+}
+
+// BFS from seed nodes with their status, bounded by the flank (bp) and the hops
+fn reach_undirected<G: GraphView + ?Sized>(
+    seeds: &[Seed],
+    options: &ExtractOptions,
+    g: &G,
+) -> Reach {
+    let (mut reach, mut visited) = Reach::from_seeds(seeds);
+    let mut visited_edges = HashSet::new();
+    let mut queue: VecDeque<_> = reach
+        .nodes
+        .iter()
+        .map(|seed| (seed.node_idx, 0, 0))
+        .collect();
+
+    // BFS, the flank is counted from the edge of the seeds so their own length is left out
+    while let Some((current_idx, dist_so_far, step_so_far)) = queue.pop_front() {
+        let current_cost = match step_so_far {
+            0 => 0,
+            _ => g.get_seq_len(current_idx).unwrap_or(0),
+        };
+
+        // check incident edges to adding nodes and edges
+        for (edge_idx, from_idx, to_idx, edge_data) in g.edges(current_idx) {
+            let neighbor_idx = if from_idx == current_idx {
+                to_idx
+            } else {
+                from_idx
+            };
+            let new_distance = dist_so_far + current_cost;
+            let new_step = step_so_far + 1;
+
+            if options.within(new_distance, new_step) {
+                // if neighbor not visited, add it to queue
+                if visited.insert(neighbor_idx) {
+                    queue.push_back((neighbor_idx, new_distance, new_step));
+                    reach.push(neighbor_idx, None, new_distance, new_step);
+                }
+
+                // keep the edge, in the orientation of the original link
+                if visited_edges.insert(edge_idx) {
+                    reach.edges.push((edge_idx, from_idx, to_idx, edge_data));
                 }
             }
         }
     }
+    reach.link_seeds(g, &mut visited_edges);
 
-    // BFS
-    while let Some((current_idx, dist_so_far, step_so_far)) = queue.pop_front() {
-        let current_cost = g
-            .get_node_data(current_idx)
-            .map(|data| data.sequence.len())
-            .unwrap_or(0);
-
-        let current_id = g
-            .get_node_data(current_idx)
-            .map(|data| data.id.clone())
-            .ok_or_else(|| anyhow::anyhow!("Node data not found"))?;
-
-        // check neighbors to adding nodes and edges
-        for neighbor_idx in g.neighbors(current_idx) {
-            if let Some(neighbor_data) = g.get_node_data(neighbor_idx) {
-                let new_distance = dist_so_far + current_cost;
-                let new_step = step_so_far + 1;
-
-                if new_distance <= max_distance || new_step <= max_step {
-                    // if neighbor not visited, add it to queue
-                    if !visited.contains(&neighbor_idx) {
-                        visited.insert(neighbor_idx);
-                        queue.push_back((neighbor_idx, new_distance, new_step));
-
-                        // add node to subgraph
-                        subgraph.add_node(
-                            neighbor_data.id.clone(),
-                            neighbor_data.sequence.clone(),
-                            "REF".to_string(),
-                        )?;
-                    }
-
-                    // add edge to subgraph
-                    subgraph.add_edge(&current_id, &neighbor_data.id)?;
+    reach
+}
+
+// BFS over node sides from seed nodes: a node entered on one side is only left through the
+// other, so the context does not turn back through unrelated links of a boundary node
+fn reach_by_sides<G: GraphView + ?Sized>(seeds: &[Seed], options: &ExtractOptions, g: &G) -> Reach {
+    let (mut reach, mut added) = Reach::from_seeds(seeds);
+    let mut visited = HashSet::new();
+    let mut visited_edges = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    // seeds are left through both sides
+    for seed in reach.nodes.iter() {
+        for orient in [Orientation::Forward, Orientation::Backward] {
+            visited.insert((seed.node_idx, orient));
+            queue.push_back(((seed.node_idx, orient), 0, 0));
+        }
+    }
+
+    while let Some((current, dist_so_far, step_so_far)) = queue.pop_front() {
+        let current_cost = match step_so_far {
+            0 => 0,
+            _ => g.get_seq_len(current.0).unwrap_or(0),
+        };
+        for (edge_idx, from_idx, to_idx, edge_data) in g.edges(current.0) {
+            let Some(next) = edge_data.step_from(from_idx, to_idx, current) else {
+                continue;
+            };
+            let new_distance = dist_so_far + current_cost;
+            let new_step = step_so_far + 1;
+            if !options.within(new_distance, new_step) {
+                continue;
+            }
+            if added.insert(next.0) {
+                reach.push(next.0, None, new_distance, new_step);
+            }
+            if visited.insert(next) {
+                queue.push_back((next, new_distance, new_step));
+            }
+            if visited_edges.insert(edge_idx) {
+                reach.edges.push((edge_idx, from_idx, to_idx, edge_data));
+            }
+        }
+    }
+    reach.link_seeds(g, &mut visited_edges);
+
+    reach
+}
+
+// the steps of the reference path from the bubble source to its sink and flanks on both
+// sides, with the bubble nodes and the links between all of them; an error if no path of the
+// reference walks both
+fn reach_along_path<G: GraphView + ?Sized>(
+    bubble: &BubbleVariant,
+    seeds: &[Seed],
+    options: &ExtractOptions,
+    g: &G,
+) -> Result<Reach> {
+    let Some(reference) = options.reference.as_deref() else {
+        return Err(anyhow::anyhow!(
+            "Reference walks need a reference sample or path (--reference)"
+        ));
+    };
+    let ref_nodes = bubble.get_ref_nodes(true);
+    let (Some(source), Some(sink)) = (
+        ref_nodes.first().and_then(|node| g.get_node_idx(node)),
+        ref_nodes.last().and_then(|node| g.get_node_idx(node)),
+    ) else {
+        return Err(anyhow::anyhow!("Bubble {} is not in the graph", bubble.id));
+    };
+    let (path, lo, hi) = reference_span(g, reference, source, sink).ok_or_else(|| {
+        anyhow::anyhow!(
+            "No path of reference {} walks both ends of bubble {}",
+            reference,
+            bubble.id
+        )
+    })?;
+    let steps: Vec<NodeIndex> = g.paths()[path as usize]
+        .steps()
+        .map(|(node_idx, _)| node_idx)
+        .collect();
+
+    // bubble nodes first, then the path between source and sink and the flanks
+    let (mut reach, mut added) = Reach::from_seeds(seeds);
+    for &node_idx in steps[lo..=hi].iter() {
+        if added.insert(node_idx) {
+            reach.push(node_idx, None, 0, 0);
+        }
+    }
+    let len = |node_idx: NodeIndex| g.get_seq_len(node_idx).unwrap_or(0);
+    // the flank is the bp between the source or sink and a step
+    let (mut distance, mut step) = (0, 0);
+    for &node_idx in steps[..lo].iter().rev() {
+        step += 1;
+        if !options.within(distance, step) {
+            break;
+        }
+        if added.insert(node_idx) {
+            reach.push(node_idx, None, distance, step);
+        }
+        distance += len(node_idx);
+    }
+    let (mut distance, mut step) = (0, 0);
+    for &node_idx in steps[hi + 1..].iter() {
+        step += 1;
+        if !options.within(distance, step) {
+            break;
+        }
+        if added.insert(node_idx) {
+            reach.push(node_idx, None, distance, step);
+        }
+        distance += len(node_idx);
+    }
+
+    let mut visited_edges = HashSet::new();
+    for &node_idx in added.iter() {
+        for (edge_idx, from_idx, to_idx, edge_data) in g.edges(node_idx) {
+            if added.contains(&from_idx)
+                && added.contains(&to_idx)
+                && visited_edges.insert(edge_idx)
+            {
+                reach.edges.push((edge_idx, from_idx, to_idx, edge_data));
+            }
+        }
+    }
+
+    Ok(reach)
+}
+
+// the path of a reference sample (or sample#haplotype, or path name) walking the source and
+// the sink, with the closest pair of their steps, a path can walk a node more than once
+fn reference_span<G: GraphView + ?Sized>(
+    g: &G,
+    reference: &str,
+    source: NodeIndex,
+    sink: NodeIndex,
+) -> Option<(u32, usize, usize)> {
+    let is_reference = |path: u32| {
+        let path = &g.paths()[path as usize];
+        path.sample == reference || path.name == reference || path.haplotype_name() == reference
+    };
+    let mut best: Option<(u32, usize, usize)> = None;
+    for &(path, a) in g.path_occurrences(source) {
+        if !is_reference(path) {
+            continue;
+        }
+        for &(other, b) in g.path_occurrences(sink) {
+            if other != path {
+                continue;
+            }
+            let (lo, hi) = (a.min(b) as usize, a.max(b) as usize);
+            if best.is_none_or(|(_, best_lo, best_hi)| hi - lo < best_hi - best_lo) {
+                best = Some((path, lo, hi));
+            }
+        }
+    }
+    best
+}
+
+// copy the reached nodes closest to the seeds first until a node, edge or bp budget would be
+// exceeded, seeds are always kept; kept nodes next to a dropped one get the count of their
+// dropped neighbours, and are BOUNDARY unless they are seeds. Other context nodes are FLANK
+// when a path through an anchor node walks them (or the graph has no paths), OFF_PATH otherwise
+fn build_subgraph<G: GraphView + ?Sized>(
+    reach: Reach,
+    anchors: &[NodeIndex],
+    options: &ExtractOptions,
+    g: &G,
+) -> Result<GFAGraph> {
+    let anchor_paths: HashSet<u32> = anchors
+        .iter()
+        .flat_map(|&node_idx| g.path_occurrences(node_idx).iter().map(|&(path, _)| path))
+        .collect();
+    let context_role = |node_idx: NodeIndex| {
+        let walked = g
+            .path_occurrences(node_idx)
+            .iter()
+            .any(|(path, _)| anchor_paths.contains(path));
+        if walked || g.paths().is_empty() {
+            NodeRole::Flank
+        } else {
+            NodeRole::OffPath
+        }
+    };
+
+    let mut incident: HashMap<NodeIndex, Vec<usize>> = HashMap::new();
+    for (i, (_, from_idx, to_idx, _)) in reach.edges.iter().enumerate() {
+        incident.entry(*from_idx).or_default().push(i);
+        if from_idx != to_idx {
+            incident.entry(*to_idx).or_default().push(i);
+        }
+    }
+
+    // the stable sort keeps the BFS order of equally distant nodes
+    let mut order: Vec<usize> = (0..reach.nodes.len()).collect();
+    order.sort_by_key(|&i| {
+        let node = &reach.nodes[i];
+        (i >= reach.seeds, node.distance, node.step)
+    });
+
+    let mut kept = HashSet::new();
+    let (mut kept_edges, mut kept_bp) = (0, 0);
+    let mut cut = order.len();
+    for (rank, &i) in order.iter().enumerate() {
+        let node_idx = reach.nodes[i].node_idx;
+        let new_edges = incident.get(&node_idx).map_or(0, |edges| {
+            edges
+                .iter()
+                .filter(|&&e| {
+                    let (_, from_idx, to_idx, _) = &reach.edges[e];
+                    let other = if *from_idx == node_idx {
+                        to_idx
+                    } else {
+                        from_idx
+                    };
+                    *other == node_idx || kept.contains(other)
+                })
+                .count()
+        });
+        let len = g.get_seq_len(node_idx).unwrap_or(0);
+        let over_budget = options.max_nodes.is_some_and(|cap| kept.len() >= cap)
+            || options
+                .max_edges
+                .is_some_and(|cap| kept_edges + new_edges > cap)
+            || options.max_bp.is_some_and(|cap| kept_bp + len > cap);
+        if i >= reach.seeds && over_budget {
+            cut = rank;
+            break;
+        }
+        kept.insert(node_idx);
+        kept_edges += new_edges;
+        kept_bp += len;
+    }
+
+    // kept nodes next to a dropped one, with the dropped neighbours they would have been
+    // linked to
+    let mut cut_nodes: HashMap<NodeIndex, HashSet<NodeIndex>> = HashMap::new();
+    for &i in order[cut..].iter() {
+        let node_idx = reach.nodes[i].node_idx;
+        for &e in incident.get(&node_idx).into_iter().flatten() {
+            let (_, from_idx, to_idx, _) = reach.edges[e];
+            for other in [from_idx, to_idx] {
+                if kept.contains(&other) {
+                    cut_nodes.entry(other).or_default().insert(node_idx);
                 }
             }
         }
     }
 
+    let mut subgraph = GFAGraph::new();
+    for &i in order[..cut].iter() {
+        let node = &reach.nodes[i];
+        let hidden = cut_nodes.get(&node.node_idx).map_or(0, HashSet::len);
+        let (role, status) = match &node.label {
+            Some((role, status)) => (*role, status.clone()),
+            None if hidden > 0 => (NodeRole::Boundary, NodeRole::Boundary.to_string()),
+            None => {
+                let role = context_role(node.node_idx);
+                (role, role.to_string())
+            }
+        };
+        copy_node(&mut subgraph, g, node.node_idx, (role, &status), hidden)?;
+    }
+    for (_, from_idx, to_idx, edge_data) in reach.edges {
+        if kept.contains(&from_idx) && kept.contains(&to_idx) {
+            copy_edge(&mut subgraph, g, from_idx, to_idx, edge_data)?;
+        }
+    }
+
     Ok(subgraph)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfa::gfa1_from_reader;
+    use crate::vcf::Traversal;
+    use std::str::FromStr;
+
+    fn bubble(ats: &[&str]) -> BubbleVariant {
+        let ats = ats.iter().map(|at| Traversal::from_str(at).unwrap());
+        BubbleVariant::new("v1".to_string(), 2, ats.collect()).unwrap()
+    }
+
+    fn names(sub: &GFAGraph) -> Vec<String> {
+        let mut names: Vec<String> = sub
+            .node_indices()
+            .map(|idx| String::from_utf8_lossy(&sub.get_id(idx).unwrap()).into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_combine_mode_all() {
+        // 1 - 2 - 3 - (4 | 5) - 6 - 7 - 8, segment 2 is 10 bp long
+        let gfa = "H\tVN:Z:1.0\nS\t1\tA\nS\t2\tAAAAAAAAAA\nS\t3\tC\nS\t4\tG\nS\t5\tT\nS\t6\tC\n\
+                   S\t7\tA\nS\t8\tA\nL\t1\t+\t2\t+\t0M\nL\t2\t+\t3\t+\t0M\nL\t3\t+\t4\t+\t0M\n\
+                   L\t3\t+\t5\t+\t0M\nL\t4\t+\t6\t+\t0M\nL\t5\t+\t6\t+\t0M\nL\t6\t+\t7\t+\t0M\n\
+                   L\t7\t+\t8\t+\t0M\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubble = bubble(&[">3>4>6", ">3>5>6"]);
+        let nodes = |options: ExtractOptions| {
+            names(&extract_subgraph_by_bfs_with(&bubble, &[], &options, &g).unwrap())
+        };
+
+        let hops = |hops| ExtractOptions {
+            hops: Some(hops),
+            ..Default::default()
+        };
+        assert_eq!(nodes(hops(0)), ["3", "4", "5", "6"]);
+        assert_eq!(nodes(hops(1)), ["2", "3", "4", "5", "6", "7"]);
+        // the 10 bp of segment 2 stop the flank on the left only
+        let flank = ExtractOptions {
+            flank: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(nodes(flank.clone()), ["2", "3", "4", "5", "6", "7", "8"]);
+
+        // both limits apply by default, any lets the hops reach past the flank
+        assert_eq!(ExtractOptions::default().combine, CombineMode::All);
+        let both = ExtractOptions {
+            hops: Some(2),
+            ..flank
+        };
+        assert_eq!(nodes(both.clone()), ["2", "3", "4", "5", "6", "7", "8"]);
+        let either = ExtractOptions {
+            combine: CombineMode::Any,
+            ..both
+        };
+        assert_eq!(nodes(either), ["1", "2", "3", "4", "5", "6", "7", "8"]);
+    }
+
+    #[test]
+    fn test_long_boundaries() {
+        // 0 - 1 - (2 |) - 3 - 4, a deletion between two 10 bp boundaries
+        let gfa = "H\tVN:Z:1.0\nS\t0\tA\nS\t1\tAAAAAAAAAA\nS\t2\tC\nS\t3\tGGGGGGGGGG\n\
+                   S\t4\tT\nL\t0\t+\t1\t+\t0M\nL\t1\t+\t2\t+\t0M\nL\t2\t+\t3\t+\t0M\n\
+                   L\t1\t+\t3\t+\t0M\nL\t3\t+\t4\t+\t0M\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubble = bubble(&[">1>2>3", ">1>3"]);
+        let links = |options: ExtractOptions| {
+            let sub = extract_subgraph_by_bfs_with(&bubble, &[], &options, &g).unwrap();
+            let name = |idx| String::from_utf8_lossy(&sub.get_id(idx).unwrap()).into_owned();
+            let mut links: Vec<(String, String)> = sub
+                .node_indices()
+                .flat_map(|idx| sub.edges(idx))
+                .map(|(_, from_idx, to_idx, _)| (name(from_idx), name(to_idx)))
+                .collect();
+            links.sort();
+            links.dedup();
+            links
+        };
+        let link = |from: &str, to: &str| (from.to_string(), to.to_string());
+
+        // the flank of 1 bp starts at the boundaries, their length does not count
+        assert_eq!(
+            links(ExtractOptions::default()),
+            [
+                link("0", "1"),
+                link("1", "2"),
+                link("1", "3"),
+                link("2", "3"),
+                link("3", "4")
+            ]
+        );
+        // the links of the bubble are kept without any context
+        let bubble_links = [link("1", "2"), link("1", "3"), link("2", "3")];
+        let hops = ExtractOptions {
+            hops: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(links(hops), bubble_links);
+        let flank = ExtractOptions {
+            flank: Some(0),
+            hops: Some(0),
+            walk: WalkMode::Sided,
+            ..Default::default()
+        };
+        assert_eq!(links(flank), bubble_links);
+    }
+
+    #[test]
+    fn test_budget_boundary() {
+        // 2 - (3 | 4) - 5 - 6 - 7 - 8, the context grows by 2, 3 and 4 bp
+        let gfa = "H\tVN:Z:1.0\nS\t2\tA\nS\t3\tC\nS\t4\tG\nS\t5\tT\nS\t6\tAA\nS\t7\tAAA\n\
+                   S\t8\tAAAA\nL\t2\t+\t3\t+\t0M\nL\t2\t+\t4\t+\t0M\nL\t3\t+\t5\t+\t0M\n\
+                   L\t4\t+\t5\t+\t0M\nL\t5\t+\t6\t+\t0M\nL\t6\t+\t7\t+\t0M\nL\t7\t+\t8\t+\t0M\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubble = bubble(&[">2>3>5", ">2>4>5"]);
+        let extract = |options: ExtractOptions| {
+            let options = ExtractOptions {
+                flank: Some(100),
+                ..options
+            };
+            extract_subgraph_by_bfs_with(&bubble, &[], &options, &g).unwrap()
+        };
+        let hidden =
+            |sub: &GFAGraph, id: &str| sub.get_node_data_by_id(id.as_bytes()).unwrap().hidden;
+
+        // the furthest nodes are dropped first and the nodes next to them marked
+        let sub = extract(ExtractOptions {
+            max_bp: Some(9),
+            ..Default::default()
+        });
+        assert_eq!(names(&sub), ["2", "3", "4", "5", "6", "7"]);
+        assert_eq!((hidden(&sub, "6"), hidden(&sub, "7")), (0, 1));
+        let role = |sub: &GFAGraph, id: &str| sub.get_node_data_by_id(id.as_bytes()).unwrap().role;
+        assert_eq!(role(&sub, "6"), Some(NodeRole::Flank));
+        assert_eq!(role(&sub, "7"), Some(NodeRole::Boundary));
+        let sub = extract(ExtractOptions {
+            max_edges: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(sub.edge_count(), 5);
+        assert_eq!(names(&sub), ["2", "3", "4", "5", "6"]);
+        assert_eq!(hidden(&sub, "6"), 1);
+        let sub = extract(ExtractOptions {
+            max_nodes: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(sub.node_count(), 5);
+        // the bubble nodes are kept whatever the cap
+        let sub = extract(ExtractOptions {
+            max_nodes: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(names(&sub), ["2", "3", "4", "5"]);
+        assert_eq!(hidden(&sub, "5"), 1);
+        assert_eq!(role(&sub, "5"), Some(NodeRole::Endpoint));
+    }
+
+    #[test]
+    fn test_sided_walk() {
+        // 1 - 2 - 3 - (4 | 5) - 6, 9 hangs off the end of 2, the side the context comes in through
+        let gfa = "H\tVN:Z:1.0\nS\t1\tA\nS\t2\tA\nS\t3\tC\nS\t4\tG\nS\t5\tT\nS\t6\tC\nS\t9\tT\n\
+                   L\t1\t+\t2\t+\t0M\nL\t2\t+\t3\t+\t0M\nL\t3\t+\t4\t+\t0M\nL\t3\t+\t5\t+\t0M\n\
+                   L\t4\t+\t6\t+\t0M\nL\t5\t+\t6\t+\t0M\nL\t2\t+\t9\t+\t0M\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubble = bubble(&[">3>4>6", ">3>5>6"]);
+        let nodes = |walk| {
+            let options = ExtractOptions {
+                hops: Some(2),
+                walk,
+                ..Default::default()
+            };
+            names(&extract_subgraph_by_bfs_with(&bubble, &[], &options, &g).unwrap())
+        };
+        assert_eq!(
+            nodes(WalkMode::Undirected),
+            ["1", "2", "3", "4", "5", "6", "9"]
+        );
+        assert_eq!(nodes(WalkMode::Sided), ["1", "2", "3", "4", "5", "6"]);
+
+        // 9 is not reached by the sided walk, so only 1 dropped by the cap is hidden from 2
+        let options = ExtractOptions {
+            hops: Some(2),
+            max_nodes: Some(5),
+            walk: WalkMode::Sided,
+            ..Default::default()
+        };
+        let sub = extract_subgraph_by_bfs_with(&bubble, &[], &options, &g).unwrap();
+        assert_eq!(names(&sub), ["2", "3", "4", "5", "6"]);
+        assert_eq!(sub.get_node_data_by_id(b"2").unwrap().hidden, 1);
+    }
+
+    #[test]
+    fn test_reference_walk() {
+        // 1 - 2 - (3 | 4) - 5 - 6, the reference walks 3, and 7 branches off 5 beside it
+        let gfa = "H\tVN:Z:1.0\nS\t1\tA\nS\t2\tA\nS\t3\tC\nS\t4\tG\nS\t5\tT\nS\t6\tC\nS\t7\tG\n\
+                   L\t1\t+\t2\t+\t0M\nL\t2\t+\t3\t+\t0M\nL\t2\t+\t4\t+\t0M\nL\t3\t+\t5\t+\t0M\n\
+                   L\t4\t+\t5\t+\t0M\nL\t5\t+\t6\t+\t0M\nL\t5\t+\t7\t+\t0M\n\
+                   P\tREF#0#chr1\t1+,2+,3+,5+,6+\t*\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubble = bubble(&[">2>3>5", ">2>4>5"]);
+        let options = ExtractOptions {
+            hops: Some(1),
+            walk: WalkMode::Reference,
+            reference: Some("REF".to_string()),
+            ..Default::default()
+        };
+        let sub = extract_subgraph_by_bfs_with(&bubble, &[], &options, &g).unwrap();
+        assert_eq!(names(&sub), ["1", "2", "3", "4", "5", "6"]);
+        assert_eq!(sub.edge_count(), 6);
+
+        // without a reference, or with one that does not walk the bubble, it is an error
+        let unset = ExtractOptions {
+            reference: None,
+            ..options.clone()
+        };
+        assert!(extract_subgraph_by_bfs_with(&bubble, &[], &unset, &g).is_err());
+        let missing = ExtractOptions {
+            reference: Some("HG002".to_string()),
+            ..options
+        };
+        assert!(extract_subgraph_by_bfs_with(&bubble, &[], &missing, &g).is_err());
+    }
+
+    #[test]
+    fn test_reference_walk_other_paths() {
+        // 1 - 2 - (3 | 4) - 5 - 6 - 7, a haplotype through 4 and 7 comes first, and the
+        // reference walks 2 twice before the bubble
+        let gfa = "H\tVN:Z:1.0\nS\t1\tA\nS\t2\tA\nS\t3\tC\nS\t4\tG\nS\t5\tT\nS\t6\tC\nS\t7\tG\n\
+                   L\t1\t+\t2\t+\t0M\nL\t2\t+\t2\t+\t0M\nL\t2\t+\t3\t+\t0M\nL\t2\t+\t4\t+\t0M\n\
+                   L\t3\t+\t5\t+\t0M\nL\t4\t+\t5\t+\t0M\nL\t5\t+\t6\t+\t0M\nL\t5\t+\t7\t+\t0M\n\
+                   W\tHG002\t1\tchr1\t0\t4\t>2>4>5>7\n\
+                   W\tREF\t0\tchr1\t0\t7\t>1>2>2>3>5>6\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubble = bubble(&[">2>3>5", ">2>4>5"]);
+        let options = ExtractOptions {
+            hops: Some(1),
+            walk: WalkMode::Reference,
+            reference: Some("REF".to_string()),
+            ..Default::default()
+        };
+        let sub = extract_subgraph_by_bfs_with(&bubble, &[], &options, &g).unwrap();
+        // the flank follows the reference to the 2 before the source, not the haplotype to 7
+        assert_eq!(names(&sub), ["2", "3", "4", "5", "6"]);
+    }
+
+    #[test]
+    fn test_node_roles() {
+        // 1 - 3 - (4 | 5) - 6 with 9 linked to 1 off the reference path
+        let gfa = "H\tVN:Z:1.0\nS\t1\tA\nS\t3\tC\nS\t4\tG\nS\t5\tT\nS\t6\tC\nS\t9\tT\n\
+                   L\t1\t+\t3\t+\t0M\nL\t3\t+\t4\t+\t0M\nL\t3\t+\t5\t+\t0M\nL\t4\t+\t6\t+\t0M\n\
+                   L\t5\t+\t6\t+\t0M\nL\t9\t+\t1\t+\t0M\n\
+                   P\tREF#0#chr1\t1+,3+,4+,6+\t*\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubble = bubble(&[">3>4>6", ">3>5>6"]);
+        let options = ExtractOptions {
+            hops: Some(2),
+            ..Default::default()
+        };
+        let sub = extract_subgraph_by_bfs_with(&bubble, &[], &options, &g).unwrap();
+        let role = |id: &str| {
+            let node = sub.get_node_data_by_id(id.as_bytes()).unwrap();
+            (node.role.unwrap(), node.status)
+        };
+        // roles from the alleles, and from the paths through the bubble for the context
+        assert_eq!(role("3"), (NodeRole::Endpoint, "ENDPOINT".to_string()));
+        assert_eq!(role("6"), (NodeRole::Endpoint, "ENDPOINT".to_string()));
+        assert_eq!(role("4"), (NodeRole::Ref, "REF".to_string()));
+        assert_eq!(role("5"), (NodeRole::Alt, "ALT1".to_string()));
+        assert_eq!(role("1"), (NodeRole::Flank, "FLANK".to_string()));
+        assert_eq!(role("9"), (NodeRole::OffPath, "OFF_PATH".to_string()));
+    }
+
+    #[test]
+    fn test_region_roles() {
+        // 1 - 2 - 3, only 2 has a stable coordinate
+        let gfa = "H\tVN:Z:1.0\nS\t1\tA\nS\t2\tCC\tSN:Z:chr1\tSO:i:0\tSR:i:0\nS\t3\tG\n\
+                   L\t1\t+\t2\t+\t0M\nL\t2\t+\t3\t+\t0M\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let options = ExtractOptions::default();
+        let sub = extract_subgraph_by_region(&"chr1:1-2".parse().unwrap(), &options, &g).unwrap();
+        assert_eq!(names(&sub), ["1", "2", "3"]);
+        let role = |id: &str| {
+            let node = sub.get_node_data_by_id(id.as_bytes()).unwrap();
+            (node.role.unwrap(), node.status)
+        };
+        assert_eq!(role("2"), (NodeRole::Region, "REGION".to_string()));
+        assert_eq!(role("1"), (NodeRole::Flank, "FLANK".to_string()));
+
+        // the caller's options apply, and a whole contig is refused
+        let options = ExtractOptions {
+            hops: Some(1),
+            max_nodes: Some(2),
+            ..Default::default()
+        };
+        let sub = extract_subgraph_by_region(&"chr1:1-2".parse().unwrap(), &options, &g).unwrap();
+        assert_eq!(sub.node_count(), 2);
+        assert!(extract_subgraph_by_region(&"chr1".parse().unwrap(), &options, &g).is_err());
+    }
+}
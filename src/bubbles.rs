@@ -0,0 +1,601 @@
+// src/bubbles.rs
+use anyhow::Result;
+use petgraph::graph::NodeIndex;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufWriter, Write};
+
+use crate::gfa::{reverse_complement, GFAGraph, GraphView, Orientation, SequenceMode};
+use crate::path::allele_support;
+use crate::source::load_graph;
+use crate::vcf::{BubbleVariant, Traversal};
+
+// a segment walked on one strand
+pub type Handle = (NodeIndex, Orientation);
+
+// limits of the superbubble search and the reference used for REF, CHROM and POS
+#[derive(Debug, Clone)]
+pub struct BubbleOptions {
+    // sample (or sample#haplotype) whose paths give REF and the coordinates, rGFA SN/SO tags
+    // come first, the sample of the first path is used if None
+    pub reference: Option<String>,
+    // segments visited from an entrance before giving up
+    pub max_nodes: usize,
+    // traversals listed per bubble
+    pub max_alleles: usize,
+}
+
+impl Default for BubbleOptions {
+    fn default() -> Self {
+        Self {
+            reference: None,
+            max_nodes: 1000,
+            max_alleles: 32,
+        }
+    }
+}
+
+// a superbubble between an entrance and an exit handle
+#[derive(Debug, Clone)]
+pub struct Superbubble {
+    pub entrance: Handle,
+    pub exit: Handle,
+    // segments strictly between the entrance and the exit
+    pub inside: Vec<NodeIndex>,
+}
+
+fn flip((node, orient): Handle) -> Handle {
+    (node, orient.flip())
+}
+
+fn handle_key((node, orient): Handle) -> (usize, bool) {
+    (node.index(), orient.is_reverse())
+}
+
+// handles reached by leaving a handle through its end
+fn children(g: &GFAGraph, (node, orient): Handle) -> Vec<Handle> {
+    let mut children: Vec<Handle> = g
+        .edges(node)
+        .into_iter()
+        .filter_map(|(_, from, to, data)| data.step_from(from, to, (node, orient)))
+        .collect();
+    children.sort_by_key(|&h| handle_key(h));
+    children.dedup();
+    children
+}
+
+fn parents(g: &GFAGraph, handle: Handle) -> Vec<Handle> {
+    children(g, flip(handle)).into_iter().map(flip).collect()
+}
+
+// the smallest superbubble entered through s (Onodera et al. 2013), on the handles of the
+// bidirected graph
+fn superbubble_from(g: &GFAGraph, s: Handle, max_nodes: usize) -> Option<Superbubble> {
+    let mut stack = vec![s];
+    let mut visited: HashSet<Handle> = HashSet::new();
+    let mut seen: HashSet<Handle> = HashSet::from([s]);
+    while let Some(v) = stack.pop() {
+        visited.insert(v);
+        seen.remove(&v);
+        if visited.len() > max_nodes {
+            return None;
+        }
+        let kids = children(g, v);
+        // a tip
+        if kids.is_empty() {
+            return None;
+        }
+        for u in kids {
+            // a cycle back to the entrance or inside the bubble
+            if u == s || visited.contains(&u) {
+                return None;
+            }
+            seen.insert(u);
+            if parents(g, u).iter().all(|p| visited.contains(p)) {
+                stack.push(u);
+            }
+        }
+        if stack.len() == 1 && seen.len() == 1 && seen.contains(&stack[0]) {
+            let t = stack.pop()?;
+            if children(g, t).contains(&s) {
+                return None;
+            }
+            visited.remove(&s);
+            let inside: Vec<NodeIndex> = visited.iter().map(|&(node, _)| node).collect();
+            // the boundary segments walked again on the other strand
+            if inside.contains(&s.0) || inside.contains(&t.0) {
+                return None;
+            }
+            return Some(Superbubble {
+                entrance: s,
+                exit: t,
+                inside,
+            });
+        }
+    }
+    None
+}
+
+// all superbubbles of the graph, each once whichever strand it was found on
+pub fn find_superbubbles(g: &GFAGraph, max_nodes: usize) -> Vec<Superbubble> {
+    let mut found = HashSet::new();
+    let mut bubbles = Vec::new();
+    for node in g.node_indices() {
+        for orient in [Orientation::Forward, Orientation::Backward] {
+            let s = (node, orient);
+            if children(g, s).len() < 2 {
+                continue;
+            }
+            let Some(bubble) = superbubble_from(g, s, max_nodes) else {
+                continue;
+            };
+            let key = (handle_key(bubble.entrance), handle_key(bubble.exit));
+            let mirror = (
+                handle_key(flip(bubble.exit)),
+                handle_key(flip(bubble.entrance)),
+            );
+            if found.insert(key.min(mirror)) {
+                bubbles.push(bubble);
+            }
+        }
+    }
+    bubbles
+}
+
+// walks from the entrance to the exit, at most max of them
+fn traversals(g: &GFAGraph, bubble: &Superbubble, max: usize) -> Vec<Vec<Handle>> {
+    fn walk(
+        g: &GFAGraph,
+        h: Handle,
+        bubble: &Superbubble,
+        inside: &HashSet<NodeIndex>,
+        path: &mut Vec<Handle>,
+        out: &mut Vec<Vec<Handle>>,
+        max: usize,
+    ) {
+        if out.len() >= max {
+            return;
+        }
+        path.push(h);
+        if h == bubble.exit {
+            out.push(path.clone());
+        } else {
+            for u in children(g, h) {
+                if u == bubble.exit || inside.contains(&u.0) {
+                    walk(g, u, bubble, inside, path, out, max);
+                }
+            }
+        }
+        path.pop();
+    }
+    let inside: HashSet<NodeIndex> = bubble.inside.iter().copied().collect();
+    let mut out = Vec::new();
+    walk(
+        g,
+        bubble.entrance,
+        bubble,
+        &inside,
+        &mut Vec::new(),
+        &mut out,
+        max,
+    );
+    out
+}
+
+// contig, 0-based start and strand of the segments on the reference paths
+struct ReferenceIndex {
+    reference: Option<String>,
+    positions: HashMap<NodeIndex, (String, usize, Orientation)>,
+}
+
+impl ReferenceIndex {
+    fn new(g: &GFAGraph, reference: Option<&str>) -> Self {
+        let reference = reference
+            .map(|r| r.to_string())
+            .or_else(|| g.paths().first().map(|path| path.sample.clone()));
+        let mut positions = HashMap::new();
+        if let Some(reference) = &reference {
+            let paths = g.paths().iter().filter(|path| {
+                path.sample == *reference
+                    || path.name == *reference
+                    || path.haplotype_name() == *reference
+            });
+            for path in paths {
+                let mut offset = path.start;
+                for (node, orient) in path.steps() {
+                    positions
+                        .entry(node)
+                        .or_insert_with(|| (path.contig.clone(), offset, orient));
+                    offset += g.get_seq_len(node).unwrap_or(0);
+                }
+            }
+        }
+        Self {
+            reference,
+            positions,
+        }
+    }
+
+    // whether a haplotype (sample#haplotype) belongs to the reference
+    fn is_reference(&self, haplotype: &str) -> bool {
+        self.reference.as_deref().is_some_and(|reference| {
+            haplotype == reference || haplotype.starts_with(&format!("{}#", reference))
+        })
+    }
+}
+
+fn step_name(g: &GFAGraph, (node, orient): Handle) -> String {
+    let arrow = if orient.is_reverse() { '<' } else { '>' };
+    let name = g.get_id(node).unwrap_or_default();
+    format!("{}{}", arrow, String::from_utf8_lossy(&name))
+}
+
+fn handle_sequence(g: &GFAGraph, (node, orient): Handle) -> String {
+    let sequence = g
+        .get_node_data(node)
+        .map(|node| node.sequence)
+        .unwrap_or_default();
+    match orient {
+        Orientation::Forward => sequence,
+        Orientation::Backward => reverse_complement(&sequence),
+    }
+}
+
+// turn a superbubble into a deconstruct-like site, None if it has a single traversal
+fn bubble_variant(
+    g: &GFAGraph,
+    bubble: &Superbubble,
+    reference: &ReferenceIndex,
+    max_alleles: usize,
+) -> Result<Option<BubbleVariant>> {
+    // walk the bubble on the strand of the reference
+    let mut bubble = bubble.clone();
+    let ref_orient = reference
+        .positions
+        .get(&bubble.entrance.0)
+        .map(|&(_, _, orient)| orient);
+    if ref_orient.is_some_and(|orient| orient != bubble.entrance.1) {
+        (bubble.entrance, bubble.exit) = (flip(bubble.exit), flip(bubble.entrance));
+    }
+    let entrance_node = g.get_node_data(bubble.entrance.0);
+
+    let mut walks = traversals(g, &bubble, max_alleles);
+    if walks.len() < 2 {
+        return Ok(None);
+    }
+    let to_traversal = |walk: &Vec<Handle>| Traversal {
+        steps: walk
+            .iter()
+            .map(|&(node, orient)| (g.get_id(node).unwrap_or_default().into_owned(), orient))
+            .collect(),
+    };
+    let id = format!(
+        "{}{}",
+        step_name(g, bubble.entrance),
+        step_name(g, bubble.exit)
+    );
+    let variant = BubbleVariant::new(id.clone(), 0, walks.iter().map(to_traversal).collect())?;
+
+    // REF is the walk of the reference haplotypes, or the rGFA backbone (rank 0)
+    let support = allele_support(&variant, g);
+    let on_backbone = |walk: &Vec<Handle>| {
+        walk.iter().all(|&(node, _)| {
+            g.get_node_data(node)
+                .and_then(|node| node.stable)
+                .is_some_and(|stable| stable.rank == 0)
+        })
+    };
+    let ref_allele = support
+        .iter()
+        .position(|allele| allele.samples.iter().any(|h| reference.is_reference(h)))
+        .or_else(|| walks.iter().position(on_backbone))
+        .unwrap_or(0);
+    walks.swap(0, ref_allele);
+
+    // CHROM and 0-based start of the entrance segment, CHROM is left empty off the reference
+    let (chrom, start) = match entrance_node.as_ref().and_then(|node| node.stable.clone()) {
+        Some(stable) => (stable.name, stable.offset),
+        None => match reference.positions.get(&bubble.entrance.0) {
+            Some((contig, offset, _)) => (contig.clone(), *offset),
+            None => (String::new(), 0),
+        },
+    };
+    let entrance_len = g.get_seq_len(bubble.entrance.0).unwrap_or(0);
+
+    // alleles spell the inner steps, padded with the last entrance base if one is empty
+    let mut alleles: Vec<String> = walks
+        .iter()
+        .map(|walk| {
+            walk[1..walk.len() - 1]
+                .iter()
+                .map(|&h| handle_sequence(g, h))
+                .collect()
+        })
+        .collect();
+    let mut pos = start + entrance_len + 1;
+    if alleles.iter().any(|allele| allele.is_empty()) {
+        let base = handle_sequence(g, bubble.entrance)
+            .chars()
+            .last()
+            .unwrap_or('N');
+        for allele in alleles.iter_mut() {
+            allele.insert(0, base);
+        }
+        pos -= 1;
+    }
+
+    let mut variant = BubbleVariant::new(id, pos, walks.iter().map(to_traversal).collect())?;
+    variant.chrom = chrom;
+    variant.end = pos + alleles[0].len() - 1;
+    variant.ref_allele = alleles.remove(0);
+    variant.alt_alleles = alleles;
+    Ok(Some(variant))
+}
+
+// the nearest kept bubble around every bubble, dropped ones are skipped up the chain,
+// parents come before their children
+fn kept_parents(parents: &[Option<usize>], kept: &[bool]) -> Vec<Option<usize>> {
+    let mut kept_parents: Vec<Option<usize>> = Vec::with_capacity(parents.len());
+    for parent in parents {
+        kept_parents.push(match *parent {
+            Some(parent) if kept[parent] => Some(parent),
+            Some(parent) => kept_parents[parent],
+            None => None,
+        });
+    }
+    kept_parents
+}
+
+// bubbles of the graph as sites sorted by position, with LV and PS from their nesting
+pub fn discover_bubbles(g: &GFAGraph, options: &BubbleOptions) -> Result<Vec<BubbleVariant>> {
+    let mut bubbles = find_superbubbles(g, options.max_nodes);
+
+    // the innermost bubble of every segment, larger bubbles first
+    bubbles.sort_by_key(|bubble| std::cmp::Reverse(bubble.inside.len()));
+    let mut owner: HashMap<NodeIndex, usize> = HashMap::new();
+    for (i, bubble) in bubbles.iter().enumerate() {
+        for &node in bubble.inside.iter() {
+            owner.insert(node, i);
+        }
+    }
+    let parents: Vec<Option<usize>> = bubbles
+        .iter()
+        .map(|bubble| {
+            let entrance = owner.get(&bubble.entrance.0);
+            let exit = owner.get(&bubble.exit.0);
+            entrance.max(exit).copied()
+        })
+        .collect();
+
+    let reference = ReferenceIndex::new(g, options.reference.as_deref());
+    let mut variants: Vec<Option<BubbleVariant>> = Vec::with_capacity(bubbles.len());
+    for bubble in bubbles.iter() {
+        variants.push(bubble_variant(g, bubble, &reference, options.max_alleles)?);
+    }
+
+    // LV and PS only count the bubbles that are written
+    let kept: Vec<bool> = variants.iter().map(Option::is_some).collect();
+    let parents = kept_parents(&parents, &kept);
+    let ids: Vec<Option<String>> = variants
+        .iter()
+        .map(|v| v.as_ref().map(|v| v.id.clone()))
+        .collect();
+    for (i, variant) in variants.iter_mut().enumerate() {
+        let Some(variant) = variant else {
+            continue;
+        };
+        // the parent is always a larger bubble, so the chain ends
+        let mut level = 0;
+        let mut current = parents[i];
+        while let Some(parent) = current {
+            level += 1;
+            current = parents[parent];
+        }
+        variant.level = Some(level);
+        variant.parent = parents[i].and_then(|parent| ids[parent].clone());
+    }
+
+    // a bubble off the reference is placed at its parent site, parents come first
+    for i in 0..variants.len() {
+        let placed = match &variants[i] {
+            Some(variant) if variant.chrom.is_empty() => parents[i]
+                .and_then(|parent| variants[parent].as_ref())
+                .map(|parent| (parent.chrom.clone(), parent.pos, parent.end)),
+            _ => continue,
+        };
+        let Some(variant) = variants[i].as_mut() else {
+            continue;
+        };
+        match placed {
+            Some((chrom, pos, end)) => {
+                variant.chrom = chrom;
+                variant.pos = pos;
+                variant.end = end;
+            }
+            // the name of the entrance segment, with positions on it
+            None => {
+                let entrance = &variant.allele_traversal[0].steps[0].0;
+                variant.chrom = String::from_utf8_lossy(entrance).into_owned();
+            }
+        }
+    }
+
+    let mut variants: Vec<BubbleVariant> = variants.into_iter().flatten().collect();
+    variants.sort_by(|a, b| (&a.chrom, a.pos, &a.id).cmp(&(&b.chrom, b.pos, &b.id)));
+    Ok(variants)
+}
+
+// write sites as a VCF with the AT, LV and PS fields of vg deconstruct
+pub fn write_vcf<W: Write>(writer: &mut W, variants: &[BubbleVariant]) -> Result<()> {
+    writeln!(writer, "##fileformat=VCFv4.2")?;
+    writeln!(writer, "##source=fm3-gfa bubbles")?;
+    writeln!(
+        writer,
+        "##INFO=<ID=AT,Number=R,Type=String,Description=\"Allele Traversal as path in graph\">"
+    )?;
+    writeln!(
+        writer,
+        "##INFO=<ID=LV,Number=1,Type=Integer,Description=\"Level in the snarl tree (0=top level)\">"
+    )?;
+    writeln!(
+        writer,
+        "##INFO=<ID=PS,Number=1,Type=String,Description=\"ID of variant corresponding to parent snarl\">"
+    )?;
+    let mut contigs: Vec<&str> = Vec::new();
+    for variant in variants {
+        if !contigs.contains(&variant.chrom.as_str()) {
+            contigs.push(&variant.chrom);
+        }
+    }
+    for contig in contigs {
+        writeln!(writer, "##contig=<ID={}>", contig)?;
+    }
+    writeln!(writer, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+
+    for variant in variants {
+        let traversals: Vec<String> = variant
+            .allele_traversal
+            .iter()
+            .map(|traversal| {
+                traversal
+                    .steps
+                    .iter()
+                    .map(|(node, orient)| {
+                        let arrow = if orient.is_reverse() { '<' } else { '>' };
+                        format!("{}{}", arrow, String::from_utf8_lossy(node))
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut info = format!(
+            "AT={};LV={}",
+            traversals.join(","),
+            variant.level.unwrap_or(0)
+        );
+        if let Some(parent) = &variant.parent {
+            info.push_str(&format!(";PS={}", parent));
+        }
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t.\t.\t{}",
+            variant.chrom,
+            variant.pos,
+            variant.id,
+            variant.ref_allele,
+            variant.alt_alleles.join(","),
+            info
+        )?;
+    }
+    Ok(())
+}
+
+// find the bubbles of a graph and write them as a VCF to stdout
+pub fn bubbles(gfa_path: &str, options: &BubbleOptions, mode: SequenceMode) -> Result<()> {
+    let whole_gfa = load_graph(gfa_path, mode)?;
+    let variants = discover_bubbles(&whole_gfa, options)?;
+    eprintln!("Found {} bubbles", variants.len());
+    let mut writer = BufWriter::new(io::stdout().lock());
+    write_vcf(&mut writer, &variants)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfa::gfa1_from_reader;
+    use noodles::vcf;
+
+    #[test]
+    fn test_discover_bubbles() {
+        // 1 -> (2 | 3 -> (4 | 5) -> 6) -> 7, and 7 -> (8 | 9 reversed) -> 10
+        let gfa = "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nS\t3\tC\nS\t4\tG\nS\t5\tT\nS\t6\tAA\n\
+                   S\t7\tCC\nS\t8\tGGG\nS\t9\tTG\nS\t10\tC\n\
+                   L\t1\t+\t2\t+\t0M\nL\t2\t+\t7\t+\t0M\nL\t1\t+\t3\t+\t0M\nL\t3\t+\t4\t+\t0M\n\
+                   L\t3\t+\t5\t+\t0M\nL\t4\t+\t6\t+\t0M\nL\t5\t+\t6\t+\t0M\nL\t6\t+\t7\t+\t0M\n\
+                   L\t7\t+\t8\t+\t0M\nL\t8\t+\t10\t+\t0M\nL\t7\t+\t9\t-\t0M\nL\t9\t-\t10\t+\t0M\n\
+                   P\tREF#0#chr1\t1+,2+,7+,8+,10+\t*\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let variants = discover_bubbles(&g, &BubbleOptions::default()).unwrap();
+
+        let ids: Vec<&str> = variants.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, vec![">1>7", ">3>6", ">7>10"]);
+        let outer = &variants[0];
+        assert_eq!((outer.chrom.as_str(), outer.pos), ("chr1", 5));
+        assert_eq!(outer.ref_allele, "A");
+        assert_eq!(outer.alleles().len(), 3);
+        assert_eq!(variants[1].level, Some(1));
+        assert_eq!(variants[1].parent.as_deref(), Some(">1>7"));
+        // the reverse strand of 9 is spelled
+        assert_eq!(variants[2].alt_alleles, vec!["CA"]);
+        assert_eq!(variants[2].ref_allele, "GGG");
+
+        // the VCF reads back as the same bubbles
+        let mut vcf_text = Vec::new();
+        write_vcf(&mut vcf_text, &variants).unwrap();
+        let mut reader = vcf::io::Reader::new(&vcf_text[..]);
+        let header = reader.read_header().unwrap();
+        let reread: Vec<BubbleVariant> = reader
+            .records()
+            .map(|r| BubbleVariant::from_vcf_record(&r.unwrap(), &header).unwrap())
+            .collect();
+        assert_eq!(reread.len(), 3);
+        assert_eq!(reread[1].parent.as_deref(), Some(">1>7"));
+        assert_eq!(
+            reread[2].allele_traversal[1].steps[1].1,
+            Orientation::Backward
+        );
+    }
+
+    #[test]
+    fn test_kept_parents() {
+        // 0 holds 1 which holds 2 and 3, 1 has a single traversal and is not written
+        let parents = [None, Some(0), Some(1), Some(1), None];
+        let kept = [true, false, true, true, false];
+        assert_eq!(
+            kept_parents(&parents, &kept),
+            [None, Some(0), Some(0), Some(0), None]
+        );
+        // nothing kept above
+        assert_eq!(
+            kept_parents(&parents, &[false, false, true, true, true]),
+            [None, None, None, None, None]
+        );
+    }
+
+    #[test]
+    fn test_walk_start() {
+        // the reference walk starts at 1000 on chr1
+        let gfa = "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nS\t3\tC\nS\t4\tG\n\
+                   L\t1\t+\t2\t+\t0M\nL\t1\t+\t3\t+\t0M\nL\t2\t+\t4\t+\t0M\nL\t3\t+\t4\t+\t0M\n\
+                   W\tREF\t0\tchr1\t1000\t1006\t>1>2>4\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let variants = discover_bubbles(&g, &BubbleOptions::default()).unwrap();
+        assert_eq!(variants.len(), 1);
+        assert_eq!(
+            (variants[0].chrom.as_str(), variants[0].pos),
+            ("chr1", 1005)
+        );
+    }
+
+    #[test]
+    fn test_reverse_discovery() {
+        // 4 comes first, so the bubble is found from <4 and flipped onto the reference strand
+        let gfa = "H\tVN:Z:1.0\nS\t4\tG\tSN:Z:chr1\tSO:i:1005\tSR:i:0\n\
+                   S\t2\tA\tSN:Z:chr1\tSO:i:1004\tSR:i:0\nS\t3\tC\tSN:Z:chr1\tSO:i:1004\tSR:i:1\n\
+                   S\t1\tACGT\tSN:Z:chr1\tSO:i:1000\tSR:i:0\n\
+                   L\t1\t+\t2\t+\t0M\nL\t1\t+\t3\t+\t0M\nL\t2\t+\t4\t+\t0M\nL\t3\t+\t4\t+\t0M\n\
+                   P\tREF#0#chr1\t1+,2+,4+\t*\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubbles = find_superbubbles(&g, 1000);
+        assert_eq!(bubbles.len(), 1);
+        assert!(bubbles[0].entrance.1.is_reverse());
+
+        let variants = discover_bubbles(&g, &BubbleOptions::default()).unwrap();
+        assert_eq!(variants[0].id, ">1>4");
+        assert_eq!(
+            (variants[0].chrom.as_str(), variants[0].pos),
+            ("chr1", 1005)
+        );
+        assert_eq!(variants[0].ref_allele, "A");
+    }
+}
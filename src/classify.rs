@@ -0,0 +1,213 @@
+// src/classify.rs
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::bfs::query_at_len;
+use crate::gfa::GraphView;
+use crate::path::WalkStep;
+use crate::snarl::SnarlTree;
+use crate::vcf::BubbleVariant;
+
+// type of a bubble, from the inner steps of its alleles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum VariantClass {
+    // one base replaced by one base
+    Snp,
+    // several bases replaced by as many bases
+    Mnp,
+    Ins,
+    Del,
+    // the REF steps walked backwards on the other strand
+    Inv,
+    // anything else, alleles of different classes or bubbles with nested bubbles
+    Complex,
+}
+
+impl Display for VariantClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VariantClass::Snp => "SNP",
+            VariantClass::Mnp => "MNP",
+            VariantClass::Ins => "INS",
+            VariantClass::Del => "DEL",
+            VariantClass::Inv => "INV",
+            VariantClass::Complex => "COMPLEX",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for VariantClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "SNP" => Ok(VariantClass::Snp),
+            "MNP" => Ok(VariantClass::Mnp),
+            "INS" => Ok(VariantClass::Ins),
+            "DEL" => Ok(VariantClass::Del),
+            "INV" => Ok(VariantClass::Inv),
+            "COMPLEX" => Ok(VariantClass::Complex),
+            _ => Err(anyhow::anyhow!("Unknown variant class: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Classification {
+    pub class: VariantClass,
+    // bp: length change of an indel, length of the replaced or inverted sequence otherwise
+    pub size: usize,
+}
+
+// whether a step list walks a node more than once
+fn has_repeats(steps: &[WalkStep]) -> bool {
+    let mut seen = HashSet::new();
+    steps.iter().any(|(node, _)| !seen.insert(node))
+}
+
+// whether alt walks the ref steps backwards, each on the other strand
+fn is_inversion(ref_steps: &[WalkStep], alt_steps: &[WalkStep]) -> bool {
+    !ref_steps.is_empty()
+        && ref_steps.len() == alt_steps.len()
+        && ref_steps.iter().rev().zip(alt_steps).all(
+            |((ref_node, ref_orient), (alt_node, alt_orient))| {
+                ref_node == alt_node && ref_orient.flip() == *alt_orient
+            },
+        )
+}
+
+// whether the steps of short appear in long in the same order
+fn is_subsequence(short: &[WalkStep], long: &[WalkStep]) -> bool {
+    let mut long = long.iter();
+    short.iter().all(|step| long.any(|other| other == step))
+}
+
+// class of one ALT against REF, from the inner steps and their lengths
+fn classify_allele(
+    ref_steps: &[WalkStep],
+    ref_len: usize,
+    alt_steps: &[WalkStep],
+    alt_len: usize,
+) -> Classification {
+    let complex = Classification {
+        class: VariantClass::Complex,
+        size: ref_len.max(alt_len),
+    };
+    if is_inversion(ref_steps, alt_steps) {
+        return Classification {
+            class: VariantClass::Inv,
+            size: ref_len,
+        };
+    }
+    // a strand switch that is not a plain inversion
+    if alt_steps.iter().any(|(_, orient)| orient.is_reverse())
+        != ref_steps.iter().any(|(_, orient)| orient.is_reverse())
+    {
+        return complex;
+    }
+    if !has_repeats(alt_steps) && ref_len == alt_len && ref_len > 0 {
+        return Classification {
+            class: if ref_len == 1 {
+                VariantClass::Snp
+            } else {
+                VariantClass::Mnp
+            },
+            size: ref_len,
+        };
+    }
+    // the longer allele walks all steps of the shorter one, e.g. a tandem duplication
+    if alt_len > ref_len && is_subsequence(ref_steps, alt_steps) {
+        return Classification {
+            class: VariantClass::Ins,
+            size: alt_len - ref_len,
+        };
+    }
+    if ref_len > alt_len && is_subsequence(alt_steps, ref_steps) {
+        return Classification {
+            class: VariantClass::Del,
+            size: ref_len - alt_len,
+        };
+    }
+    complex
+}
+
+// class and size of a bubble, a bubble with children in the snarl tree is COMPLEX
+pub fn classify<G: GraphView + ?Sized>(
+    bubble: &BubbleVariant,
+    tree: Option<&SnarlTree>,
+    g: &G,
+) -> Result<Classification> {
+    let ref_steps = bubble.get_allele_steps(0, false);
+    let ref_len = query_at_len(&bubble.get_allele_nodes(0, false), g)?;
+
+    let mut classes = Vec::new();
+    for allele in 1..bubble.allele_count() {
+        let alt_len = query_at_len(&bubble.get_allele_nodes(allele, false), g)?;
+        let alt_steps = bubble.get_allele_steps(allele, false);
+        classes.push(classify_allele(&ref_steps, ref_len, &alt_steps, alt_len));
+    }
+
+    let size = classes.iter().map(|c| c.size).max().unwrap_or(0);
+    let nested = tree.is_some_and(|tree| !tree.children(&bubble.id).is_empty());
+    let class = match classes.first() {
+        // a REF only record or a bubble made of smaller bubbles
+        None => VariantClass::Complex,
+        Some(_) if nested || has_repeats(&ref_steps) => VariantClass::Complex,
+        Some(first) if classes.iter().all(|c| c.class == first.class) => first.class,
+        Some(_) => VariantClass::Complex,
+    };
+    Ok(Classification { class, size })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfa::gfa1_from_reader;
+    use crate::vcf::Traversal;
+
+    #[test]
+    fn test_classify() {
+        let gfa = "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nS\t3\tG\nS\t4\tTTT\nS\t5\tGGGGG\nS\t6\tC\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let class = |ats: &[&str]| {
+            let ats = ats.iter().map(|at| Traversal::from_str(at).unwrap());
+            let bubble = BubbleVariant::new("v1".to_string(), 5, ats.collect()).unwrap();
+            let c = classify(&bubble, None, &g).unwrap();
+            (c.class, c.size)
+        };
+
+        assert_eq!(class(&[">1>2>6", ">1>3>6"]), (VariantClass::Snp, 1));
+        assert_eq!(class(&[">1>4>6", ">1>2>3>2>6"]), (VariantClass::Complex, 3));
+        assert_eq!(class(&[">1>4>6", ">1>2>3>6"]), (VariantClass::Complex, 3));
+        assert_eq!(class(&[">1>2>3>6", ">1>3>2>6"]), (VariantClass::Mnp, 2));
+        assert_eq!(class(&[">1>6", ">1>5>6"]), (VariantClass::Ins, 5));
+        assert_eq!(class(&[">1>5>6", ">1>6"]), (VariantClass::Del, 5));
+        assert_eq!(class(&[">1>4>5>6", ">1<5<4>6"]), (VariantClass::Inv, 8));
+        assert_eq!(class(&[">1>4>6", ">1>4>4>6"]), (VariantClass::Ins, 3));
+        // one insertion and one deletion
+        assert_eq!(
+            class(&[">1>2>6", ">1>2>5>6", ">1>6"]),
+            (VariantClass::Complex, 5)
+        );
+        assert_eq!(class(&[">1>2>6", ">1>2>5>6"]), (VariantClass::Ins, 5));
+
+        let mut tree = SnarlTree::default();
+        tree.insert("v2", Some(1), Some("v1"));
+        let bubble = BubbleVariant::new(
+            "v1".to_string(),
+            5,
+            vec![
+                Traversal::from_str(">1>2>6").unwrap(),
+                Traversal::from_str(">1>3>6").unwrap(),
+            ],
+        )
+        .unwrap();
+        let nested = classify(&bubble, Some(&tree), &g).unwrap();
+        assert_eq!(nested.class, VariantClass::Complex);
+    }
+}
@@ -1,5 +1,9 @@
 use anyhow::Result;
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
+
+use crate::bfs::ExtractOptions;
+use crate::snarl::NestedContext;
+use crate::vcf::IdScheme;
 
 #[derive(Parser, Debug)]
 #[command(name = "fm3-gfa")]
@@ -17,29 +21,157 @@ pub struct Cli {
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
-    /// Generate all JSONs into a tsv file with Variant ID, stdout
+    /// Generate all JSONs into a tsv file with Variant ID, class, size, AC and AF, stdout
     Generate {
-        /// Input GFA file
+        /// Input graph (GFA or fm3-gfa index)
         #[arg(short, long, required = true, help_heading = Some("I/O"))]
         gfa: String,
-        /// Input VCF file
-        #[arg(short, long, required = true, help_heading = Some("I/O"))]
-        vcf: String,
+        /// Input VCF file, bubbles are found in the graph if it is not given
+        #[arg(short, long, help_heading = Some("I/O"))]
+        vcf: Option<String>,
+        /// Keep the genotypes of the samples listed in this file only, one per line
+        #[arg(long, help_heading = Some("I/O"))]
+        samples: Option<String>,
+        /// Variant ID index of a tabix/CSI indexed VCF, built if missing or stale
+        /// [default: <vcf>.fm3v]
+        #[arg(long, help_heading = Some("I/O"), conflicts_with = "no_id_index")]
+        id_index: Option<String>,
+        /// Keep the variant ID index in memory instead of writing it
+        #[arg(long, help_heading = Some("I/O"))]
+        no_id_index: bool,
+        /// Only bubbles overlapping a region, e.g. chr6:31,000,000-31,010,000
+        #[arg(short, long)]
+        region: Option<String>,
+        /// Also lay out the child bubbles or the parent bubble (LV/PS fields)
+        #[arg(long, value_enum)]
+        nested: Option<NestedContext>,
+        /// Name records without ID as CHROM:POS:REF:ALT (site), CHROM:POS:<hash> (hash),
+        /// or site unless the alleles are long (auto)
+        #[arg(long, value_enum, default_value = "auto")]
+        id_scheme: IdScheme,
+        #[command(flatten)]
+        extract: ExtractOptions,
         /// Threads
         #[arg(default_value = "1", short = '@', long)]
         threads: usize,
+        /// Memory-map segment sequences instead of loading them
+        #[arg(long)]
+        mmap: bool,
     },
-    /// Start a simple web server for querying and visualization
-    Serve {
-        /// Input GFA file
+    /// Find bubbles in a graph without a VCF, deconstruct-like VCF to stdout
+    Bubbles {
+        /// Input graph (GFA or fm3-gfa index)
+        #[arg(short, long, required = true, help_heading = Some("I/O"))]
+        gfa: String,
+        /// Sample or path whose walk is REF and gives CHROM and POS,
+        /// rGFA SN/SO tags come first [default: sample of the first path]
+        #[arg(short, long)]
+        reference: Option<String>,
+        /// Give up on a bubble after visiting this many segments
+        #[arg(long, default_value = "1000")]
+        max_nodes: usize,
+        /// Keep at most this many allele traversals per bubble
+        #[arg(long, default_value = "32")]
+        max_alleles: usize,
+        /// Memory-map segment sequences instead of loading them
+        #[arg(long)]
+        mmap: bool,
+    },
+    /// Write a binary index of a GFA file, pass it as --gfa to skip parsing
+    Index {
+        /// Input graph (GFA or fm3-gfa index)
+        #[arg(short, long, required = true, help_heading = Some("I/O"))]
+        gfa: String,
+        /// Output index file [default: <gfa>.fm3i]
+        #[arg(short, long, help_heading = Some("I/O"))]
+        output: Option<String>,
+        /// Store offsets into the GFA file instead of packed sequences
+        #[arg(long)]
+        mmap: bool,
+    },
+    /// Check an index against a checksum of the whole GFA it was built from
+    VerifyIndex {
+        /// Index file written by `index`
+        #[arg(short, long, required = true, help_heading = Some("I/O"))]
+        index: String,
+    },
+    /// Layout the neighbourhood of a region of rGFA stable coordinates, stdout
+    Query {
+        /// Input graph (GFA or fm3-gfa index)
+        #[arg(short, long, required = true, help_heading = Some("I/O"))]
+        gfa: String,
+        /// Region, e.g. chr6:31,000,000-31,010,000
         #[arg(short, long, required = true)]
+        region: String,
+        #[command(flatten)]
+        extract: ExtractOptions,
+        /// Memory-map segment sequences instead of loading them
+        #[arg(long)]
+        mmap: bool,
+    },
+    /// Report segment, link, component and path statistics of a graph, stdout
+    Stats {
+        /// Input graph (GFA or fm3-gfa index)
+        #[arg(short, long, required = true, help_heading = Some("I/O"))]
+        gfa: String,
+        /// Print JSON instead of a human-readable table
+        #[arg(long)]
+        json: bool,
+        /// Memory-map segment sequences instead of loading them
+        #[arg(long)]
+        mmap: bool,
+    },
+    /// Check VCF allele traversals against the graph, per-variant TSV to stdout
+    Validate {
+        /// Input graph (GFA or fm3-gfa index)
+        #[arg(short, long, required = true, help_heading = Some("I/O"))]
         gfa: String,
         /// Input VCF file
-        #[arg(short, long, required = true)]
+        #[arg(short, long, required = true, help_heading = Some("I/O"))]
         vcf: String,
+        /// Name records without ID as CHROM:POS:REF:ALT (site), CHROM:POS:<hash> (hash),
+        /// or site unless the alleles are long (auto)
+        #[arg(long, value_enum, default_value = "auto")]
+        id_scheme: IdScheme,
+        /// Memory-map segment sequences instead of loading them
+        #[arg(long)]
+        mmap: bool,
+    },
+    /// Start a simple web server for querying and visualization
+    Serve {
+        /// Input graph (GFA or fm3-gfa index)
+        #[arg(short, long, required = true)]
+        gfa: String,
+        /// Input VCF file, bubbles are found in the graph if it is not given
+        #[arg(short, long)]
+        vcf: Option<String>,
+        /// Keep the genotypes of the samples listed in this file only, one per line
+        #[arg(long)]
+        samples: Option<String>,
+        /// Variant ID index of a tabix/CSI indexed VCF, built if missing or stale
+        /// [default: <vcf>.fm3v]
+        #[arg(long, conflicts_with = "no_id_index")]
+        id_index: Option<String>,
+        /// Keep the variant ID index in memory instead of writing it
+        #[arg(long)]
+        no_id_index: bool,
+        /// Name records without ID as CHROM:POS:REF:ALT (site), CHROM:POS:<hash> (hash),
+        /// or site unless the alleles are long (auto)
+        #[arg(long, value_enum, default_value = "auto")]
+        id_scheme: IdScheme,
+        #[command(flatten)]
+        extract: ExtractOptions,
         /// Port number
         #[arg(short, long, default_value = "8888")]
         port: u16,
+        /// Memory-map segment sequences instead of loading them
+        #[arg(long)]
+        mmap: bool,
+        /// Only index line offsets of a GFA1 file and parse segments on demand,
+        /// keeping up to CACHE_SIZE of them. The first start scans the whole file
+        /// and saves the offsets to <gfa>.fm3l, later starts reuse them until the GFA changes
+        #[arg(long, value_name = "CACHE_SIZE", num_args = 0..=1, default_missing_value = "100000", conflicts_with = "mmap")]
+        lazy: Option<usize>,
     },
 }
 
@@ -1,13 +1,39 @@
-use crate::gml::GMLGraph;
-use anyhow::Result;
+use crate::{
+    bfs::{extract_subgraph_by_bfs_with, extract_subgraph_by_region, ExtractOptions},
+    gfa::{EdgeData, GFAGraph, GraphView, NodeRole, Orientation, TagValue},
+    gml::{self, GMLGraph, GMLObject, GMLValue},
+    layout::Layout,
+    path::{allele_support, AlleleSupport},
+    region::Region,
+    snarl::NestedBubble,
+    vcf::{AlleleGenotypes, BubbleVariant},
+};
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::io;
 use std::path::Path;
+use std::str::FromStr;
 
 #[derive(Serialize)]
 pub struct EchartGraph {
     nodes: Vec<Node>,
     links: Vec<Link>,
+    // alleles of a bubble with their haplotype support, empty for a region
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    alleles: Vec<AlleleSupport>,
+    // allele counts, frequencies and carriers from the VCF GT, empty without samples
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    genotypes: Vec<AlleleGenotypes>,
+    // parent or child bubbles drawn along with a bubble
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nested: Vec<NestedBubble>,
+    // a node, edge or bp budget left part of the context out, see the BOUNDARY nodes
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    // the paths of a lazily read graph could not be loaded, samples and haplotype support are unknown
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    no_paths: bool,
 }
 
 #[derive(Serialize)]
@@ -25,6 +51,17 @@ struct MyAttr {
     sequence: String,
     #[serde(rename = "Status")]
     status: String,
+    // structured role of the node, the legend category when it is not an ALT
+    #[serde(rename = "Role", default, skip_serializing_if = "Option::is_none")]
+    role: Option<NodeRole>,
+    #[serde(rename = "Samples")]
+    samples: Vec<String>,
+    // segment tags, numeric ones can drive node colour and size
+    #[serde(rename = "Tags", default, skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, TagValue>,
+    // neighbours left out where a budget cut the subgraph
+    #[serde(rename = "Hidden", default, skip_serializing_if = "Option::is_none")]
+    hidden: Option<usize>,
 }
 
 impl Default for MyAttr {
@@ -32,20 +69,101 @@ impl Default for MyAttr {
         MyAttr {
             sequence: "".to_string(),
             status: "".to_string(),
+            role: None,
+            samples: Vec::new(),
+            tags: BTreeMap::new(),
+            hidden: None,
         }
     }
 }
 
 impl MyAttr {
-    pub fn new(sequence: String, status: String) -> Self {
-        MyAttr { sequence, status }
+    pub fn new(
+        sequence: String,
+        status: String,
+        samples: Vec<String>,
+        tags: BTreeMap<String, TagValue>,
+    ) -> Self {
+        MyAttr {
+            sequence,
+            status,
+            role: None,
+            samples,
+            tags,
+            hidden: None,
+        }
     }
 }
 
+// tags read back from the GML nested object
+fn tags_from_gml(pairs: Vec<(String, GMLValue)>) -> BTreeMap<String, TagValue> {
+    pairs
+        .into_iter()
+        .filter_map(|(tag, value)| {
+            let value = match value {
+                GMLValue::GMLInt(value) => TagValue::Int(value),
+                GMLValue::GMLFloat(value) => TagValue::Float(value),
+                GMLValue::GMLString(value) => TagValue::Text(unescape(&value)),
+                GMLValue::GMLObject(_) => return None,
+            };
+            Some((tag, value))
+        })
+        .collect()
+}
+
+// undo the backslash escapes of a GML string
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
 #[derive(Serialize, Deserialize)]
 struct Link {
     source: i64,
     target: i64,
+    value: LinkAttr,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct LinkAttr {
+    #[serde(rename = "FromOrient")]
+    from_orient: String,
+    #[serde(rename = "ToOrient")]
+    to_orient: String,
+    #[serde(rename = "Overlap")]
+    overlap: String,
+    // alleles walking the link on its strands, only for a bubble
+    #[serde(rename = "Alleles", default, skip_serializing_if = "Vec::is_empty")]
+    alleles: Vec<String>,
+}
+
+impl Default for LinkAttr {
+    fn default() -> Self {
+        LinkAttr {
+            from_orient: "+".to_string(),
+            to_orient: "+".to_string(),
+            overlap: "*".to_string(),
+            alleles: Vec::new(),
+        }
+    }
+}
+
+impl LinkAttr {
+    pub fn new(from_orient: String, to_orient: String, overlap: String) -> Self {
+        LinkAttr {
+            from_orient,
+            to_orient,
+            overlap,
+            alleles: Vec::new(),
+        }
+    }
 }
 
 impl EchartGraph {
@@ -70,41 +188,192 @@ impl EchartGraph {
             links.push(Link {
                 source: edge.source,
                 target: edge.target,
+                value: LinkAttr::default(),
             });
         }
-        Ok(EchartGraph { nodes, links })
+        Ok(EchartGraph {
+            nodes,
+            links,
+            alleles: Vec::new(),
+            genotypes: Vec::new(),
+            nested: Vec::new(),
+            truncated: false,
+            no_paths: false,
+        })
     }
 
     pub fn from_gml_anno(layout_g: GMLGraph, origin_g: GMLGraph) -> Result<Self> {
-        // the order of nodes in layout_g and origin_g is the same
+        // the layout keeps node ids and edge endpoints, match both back to origin_g by id
+        if layout_g.nodes.len() != origin_g.nodes.len()
+            || layout_g.edges.len() != origin_g.edges.len()
+        {
+            bail!(
+                "Layout has {} nodes and {} edges, the subgraph has {} nodes and {} edges",
+                layout_g.nodes.len(),
+                layout_g.edges.len(),
+                origin_g.nodes.len(),
+                origin_g.edges.len()
+            );
+        }
+        let origin_nodes: HashMap<i64, &gml::Node> =
+            origin_g.nodes.iter().map(|node| (node.id, node)).collect();
+        // several links may join the same two nodes, they are taken in order
+        let mut origin_edges: HashMap<(i64, i64), VecDeque<&gml::Edge>> = HashMap::new();
+        for edge in origin_g.edges.iter() {
+            origin_edges
+                .entry((edge.source, edge.target))
+                .or_default()
+                .push_back(edge);
+        }
 
         let mut nodes = Vec::new();
         let mut links = Vec::new();
 
-        for i in 0..layout_g.nodes.len() {
-            let layout_node = &layout_g.nodes[i];
-            let origin_node = &origin_g.nodes[i];
+        for layout_node in layout_g.nodes.iter() {
             let id = layout_node.id;
+            let Some(origin_node) = origin_nodes.get(&id) else {
+                bail!("Node {} of the layout is not in the subgraph", id);
+            };
             let name = origin_node.label.clone().unwrap();
             let (x, y) = layout_node.get_graphic_pos();
 
             let sequence = origin_node.get_sequence();
             let status = origin_node.get_status();
+            let samples = origin_node.get_samples();
+            let tags = tags_from_gml(origin_node.get_tags());
+            let mut value = MyAttr::new(sequence, status, samples, tags);
+            value.role = origin_node.get_role();
+            value.hidden = Some(origin_node.get_hidden()).filter(|&hidden| hidden > 0);
             nodes.push(Node {
                 id,
                 x,
                 y,
                 name,
-                value: MyAttr::new(sequence, status),
+                value,
             });
         }
-        for edge in layout_g.edges.iter() {
+        for layout_edge in layout_g.edges.iter() {
+            let (source, target) = (layout_edge.source, layout_edge.target);
+            let Some(origin_edge) = origin_edges
+                .get_mut(&(source, target))
+                .and_then(|edges| edges.pop_front())
+            else {
+                bail!(
+                    "Edge {} -> {} of the layout is not in the subgraph",
+                    source,
+                    target
+                );
+            };
             links.push(Link {
-                source: edge.source,
-                target: edge.target,
+                source,
+                target,
+                value: LinkAttr::new(
+                    origin_edge.get_from_orient(),
+                    origin_edge.get_to_orient(),
+                    origin_edge.get_overlap(),
+                ),
             });
         }
-        Ok(EchartGraph { nodes, links })
+        let truncated = nodes.iter().any(|node| node.value.hidden.is_some());
+        Ok(EchartGraph {
+            nodes,
+            links,
+            alleles: Vec::new(),
+            genotypes: Vec::new(),
+            nested: Vec::new(),
+            truncated,
+            no_paths: false,
+        })
+    }
+
+    // extract the subgraph around a bubble, layout it by FM3 and annotate it
+    pub fn from_bubble<G: GraphView + ?Sized>(bubble: &BubbleVariant, g: &G) -> Result<Self> {
+        Self::from_bubble_nested(bubble, Vec::new(), &ExtractOptions::default(), g)
+    }
+
+    // same as from_bubble, the nodes of the nested bubbles are kept in the subgraph
+    pub fn from_bubble_nested<G: GraphView + ?Sized>(
+        bubble: &BubbleVariant,
+        nested: Vec<NestedBubble>,
+        options: &ExtractOptions,
+        g: &G,
+    ) -> Result<Self> {
+        // extract subgraph by bfs
+        let extra_nodes: Vec<String> = nested.iter().flat_map(|n| n.nodes.clone()).collect();
+        let sub_graph = extract_subgraph_by_bfs_with(bubble, &extra_nodes, options, g)?;
+        let mut echart_graph = Self::from_subgraph(&sub_graph)?;
+        echart_graph.alleles = allele_support(bubble, g);
+        echart_graph.mark_allele_links(bubble);
+        if !bubble.genotypes.is_empty() {
+            echart_graph.genotypes = bubble.genotypes.allele_stats(bubble.allele_count());
+        }
+        echart_graph.nested = nested;
+        echart_graph.no_paths = !g.paths_loaded();
+        Ok(echart_graph)
+    }
+
+    // name the alleles whose traversal walks each link
+    fn mark_allele_links(&mut self, bubble: &BubbleVariant) {
+        let names: HashMap<i64, &[u8]> = self
+            .nodes
+            .iter()
+            .map(|node| (node.id, node.name.as_bytes()))
+            .collect();
+        let allele_steps: Vec<_> = (0..bubble.allele_count())
+            .map(|allele| bubble.get_allele_steps(allele, true))
+            .collect();
+        for link in self.links.iter_mut() {
+            let (Some(&from), Some(&to)) = (names.get(&link.source), names.get(&link.target))
+            else {
+                continue;
+            };
+            let (Ok(from_orient), Ok(to_orient)) = (
+                Orientation::from_str(&link.value.from_orient),
+                Orientation::from_str(&link.value.to_orient),
+            ) else {
+                continue;
+            };
+            let data = EdgeData::new(from_orient, to_orient, "*");
+            for (allele, steps) in allele_steps.iter().enumerate() {
+                let walked = steps.windows(2).any(|pair| {
+                    data.walks(
+                        from,
+                        to,
+                        (pair[0].0.as_slice(), pair[0].1),
+                        (pair[1].0.as_slice(), pair[1].1),
+                    )
+                });
+                if walked {
+                    link.value.alleles.push(BubbleVariant::allele_name(allele));
+                }
+            }
+        }
+    }
+
+    // extract the subgraph around a region of stable coordinates and layout it
+    pub fn from_region<G: GraphView + ?Sized>(
+        region: &Region,
+        options: &ExtractOptions,
+        g: &G,
+    ) -> Result<Self> {
+        let sub_graph = extract_subgraph_by_region(region, options, g)?;
+        let mut echart_graph = Self::from_subgraph(&sub_graph)?;
+        echart_graph.no_paths = !g.paths_loaded();
+        Ok(echart_graph)
+    }
+
+    // layout a subgraph by FM3 and annotate it
+    pub fn from_subgraph(sub_graph: &GFAGraph) -> Result<Self> {
+        // GFAGraph to GMLGraph
+        let gml_content = sub_graph.to_gml_string();
+        let origin_g = GMLObject::from_str(&gml_content)?;
+        let origin_g = GMLGraph::from_gml(origin_g)?;
+        // layout by FM3
+        let layout = Layout::new()?;
+        let res = layout.run(&gml_content)?;
+        let layout_graph = GMLObject::from_str(&res)?;
+        let layout_graph = GMLGraph::from_gml(layout_graph)?;
+        Self::from_gml_anno(layout_graph, origin_g)
     }
 
     pub fn oneline_stdout(&self) -> Result<String> {
@@ -116,3 +385,40 @@ impl EchartGraph {
         std::fs::write(path, json)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gml(text: &str) -> GMLGraph {
+        GMLGraph::from_gml(GMLObject::from_str(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_gml_anno_matches_edges_by_id() {
+        let origin = gml("graph [\n\tnode [\n\t\tid 0\n\t\tlabel \"a\"\n\t]\n\tnode [\n\t\tid 1\n\t\tlabel \"b\"\n\t]\n\
+             \tedge [\n\t\tsource 0\n\t\ttarget 1\n\t\tto_orient \"-\"\n\t\toverlap \"2M\"\n\t]\n\
+             \tedge [\n\t\tsource 1\n\t\ttarget 1\n\t\toverlap \"1M\"\n\t]\n]\n");
+        // the layout returns nodes and edges in another order
+        let layout = gml("graph [\n\tnode [\n\t\tid 1\n\t]\n\tnode [\n\t\tid 0\n\t]\n\
+             \tedge [\n\t\tsource 1\n\t\ttarget 1\n\t]\n\tedge [\n\t\tsource 0\n\t\ttarget 1\n\t]\n]\n");
+        let echart = EchartGraph::from_gml_anno(layout, origin).unwrap();
+        let name = |id| &echart.nodes.iter().find(|node| node.id == id).unwrap().name;
+        assert_eq!((name(0).as_str(), name(1).as_str()), ("a", "b"));
+        let link = |source, target| {
+            let link = echart
+                .links
+                .iter()
+                .find(|link| (link.source, link.target) == (source, target));
+            &link.unwrap().value
+        };
+        assert_eq!(link(1, 1).overlap, "1M");
+        assert_eq!(link(0, 1).to_orient, "-");
+        assert_eq!(link(0, 1).overlap, "2M");
+
+        let origin = gml("graph [\n\tnode [\n\t\tid 0\n\t\tlabel \"a\"\n\t]\n]\n");
+        let layout =
+            gml("graph [\n\tnode [\n\t\tid 0\n\t]\n\tedge [\n\t\tsource 0\n\t\ttarget 0\n\t]\n]\n");
+        assert!(EchartGraph::from_gml_anno(layout, origin).is_err());
+    }
+}
@@ -2,30 +2,106 @@
 use anyhow::Result;
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use std::{str::FromStr, time::Duration};
+use std::time::Duration;
 
 use crate::{
-    bfs::extract_subgraph_by_bfs,
+    bfs::ExtractOptions,
+    bubbles::{discover_bubbles, BubbleOptions},
+    classify::classify,
     echart::EchartGraph,
-    gfa::gfa_to_graph,
-    gml::{GMLGraph, GMLObject},
-    layout::Layout,
-    vcf::parse_vcf_file,
+    gfa::{GraphView, SequenceMode},
+    region::Region,
+    snarl::{nested_bubbles, NestedContext, SnarlTree},
+    source::load_graph,
+    variants::{IdIndexPath, VariantSource},
+    vcf::{parse_vcf_file_with, read_sample_list, BubbleVariant, IdScheme},
 };
 
-pub fn generate(gfa_path: &str, vcf_path: &str, threads: usize) -> Result<()> {
+// AC and AF columns, one comma separated value per allele with REF first
+fn genotype_columns(bubble: &BubbleVariant) -> (String, String) {
+    if bubble.genotypes.is_empty() {
+        return (".".to_string(), ".".to_string());
+    }
+    let stats = bubble.genotypes.allele_stats(bubble.allele_count());
+    let counts: Vec<String> = stats.iter().map(|s| s.count.to_string()).collect();
+    let freqs: Vec<String> = stats
+        .iter()
+        .map(|s| format!("{:.4}", s.frequency))
+        .collect();
+    (counts.join(","), freqs.join(","))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn generate(
+    gfa_path: &str,
+    vcf_path: Option<&str>,
+    samples_path: Option<&str>,
+    id_index_path: &IdIndexPath,
+    region: Option<&str>,
+    nested: Option<NestedContext>,
+    scheme: IdScheme,
+    extract: &ExtractOptions,
+    threads: usize,
+    mode: SequenceMode,
+) -> Result<()> {
     // load gfa file
     let spinner = ProgressBar::new_spinner();
     spinner.set_message("Loading GFA file");
     spinner.enable_steady_tick(Duration::from_millis(100));
-    let whole_gfa = gfa_to_graph(gfa_path)?;
+    let whole_gfa = load_graph(gfa_path, mode)?;
     spinner.finish();
     let spinner = ProgressBar::new_spinner();
 
-    // load vcf file
+    // load vcf file, or find the bubbles in the graph without one
     spinner.set_message("Loading VCF file");
     spinner.enable_steady_tick(Duration::from_millis(100));
-    let bubbles = parse_vcf_file(vcf_path)?;
+    let samples = samples_path.map(read_sample_list).transpose()?;
+    let region = region
+        .map(|region| Region::parse_with(region, |contig| whole_gfa.has_contig(contig)))
+        .transpose()?;
+    let mut source = None;
+    let mut bubbles = match vcf_path {
+        // only the bubbles overlapping a region, through the VCF index if there is one
+        Some(vcf_path)
+            if region.is_some() || nested.is_some() && VariantSource::is_indexed(vcf_path) =>
+        {
+            let opened = source.insert(VariantSource::open(
+                vcf_path,
+                samples.as_deref(),
+                scheme,
+                id_index_path,
+            )?);
+            match &region {
+                Some(region) => opened.fetch(region)?,
+                None => {
+                    let mut all = Vec::new();
+                    opened.for_each(|bubble| {
+                        all.push(bubble.clone());
+                        Ok(())
+                    })?;
+                    all
+                }
+            }
+        }
+        Some(vcf_path) => parse_vcf_file_with(vcf_path, samples.as_deref(), scheme)?,
+        None => discover_bubbles(&whole_gfa, &BubbleOptions::default())?,
+    };
+    if let (None, Some(region)) = (vcf_path, &region) {
+        bubbles.retain(|bubble| bubble.overlaps(region));
+    }
+    // nested bubbles are looked up by ID, bubbles parsed without an index are kept for it
+    if nested.is_some() && source.is_none() {
+        source = Some(VariantSource::from_variants(bubbles.clone()));
+    }
+    // bubbles with children are classified as COMPLEX
+    let local_tree;
+    let tree = match &source {
+        Some(source) => source.snarl_tree()?,
+        None => {
+            local_tree = SnarlTree::from_variants(bubbles.iter());
+            &local_tree
+        }
+    };
     spinner.finish();
 
     // set progress bar style
@@ -43,21 +119,28 @@ pub fn generate(gfa_path: &str, vcf_path: &str, threads: usize) -> Result<()> {
         .progress_with_style(style)
         .for_each(|bubble| {
             match (|| -> Result<String> {
-                // extract subgraph by bfs
-                let sub_graph = extract_subgraph_by_bfs(&bubble, &whole_gfa)?;
-                // GFAGraph to GMLGraph
-                let gml_content = sub_graph.to_gml_string();
-                let origin_g = GMLObject::from_str(&gml_content)?;
-                let origin_g = GMLGraph::from_gml(origin_g)?;
-                // layout by FM3
-                let layout = Layout::new()?;
-                let res = layout.run(&gml_content)?;
-                let layout_graph = GMLObject::from_str(&res)?;
-                let layout_graph = GMLGraph::from_gml(layout_graph)?;
-                let echart_graph = EchartGraph::from_gml_anno(layout_graph, origin_g)?;
+                let nested = match (nested, &source) {
+                    (Some(context), Some(source)) => {
+                        nested_bubbles(&bubble, context, source.snarl_tree()?, |id| source.get(id))?
+                    }
+                    _ => Vec::new(),
+                };
+                // extract subgraph, layout by FM3 and annotate
+                let echart_graph =
+                    EchartGraph::from_bubble_nested(&bubble, nested, extract, &whole_gfa)?;
                 echart_graph.oneline_stdout()
             })() {
-                Ok(line) => println!("{}\t{}", bubble.id, line),
+                Ok(line) => {
+                    let (counts, freqs) = genotype_columns(&bubble);
+                    let (class, size) = match classify(&bubble, Some(tree), &whole_gfa) {
+                        Ok(c) => (c.class.to_string(), c.size.to_string()),
+                        Err(_) => (".".to_string(), ".".to_string()),
+                    };
+                    println!(
+                        "{}\t{}\t{}\t{}\t{}\t{}",
+                        bubble.id, class, size, counts, freqs, line
+                    )
+                }
                 Err(e) => eprintln!("Error processing bubble: {}", e),
             }
         });
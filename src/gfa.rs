@@ -1,41 +1,523 @@
+use crate::gfa2::gfa2_to_graph;
+use crate::path::{parse_path_line, parse_walk_line, PathData, PathIndex, WalkStep};
+use crate::region::{CoordIndex, Region, StableCoord};
+use crate::source::{compression, open_text, Compression};
+use crate::store::{SegmentNames, SegmentTags, SequenceStore};
 use anyhow::Result;
-use gfa::{
-    gfa::{SegmentId, GFA},
-    parser::*,
-};
+use gfa::optfields::{OptField, OptFieldVal};
+use memmap2::Mmap;
 use petgraph::{
-    graph::{Graph, NodeIndex, UnGraph},
+    graph::{EdgeIndex, Graph, NodeIndex, UnGraph},
     visit::EdgeRef,
 };
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::BufRead;
+use std::str::FromStr;
 // use petgraph::visit::NodeIndexable;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+// all attributes of a node, assembled on demand from the graph store
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct NodeData {
     pub id: Vec<u8>,
     pub sequence: String,
-    // REF or ALT
+    // allele name (REF, ALT1 ...) or role of the node, shown in the legend
     pub status: String,
+    // what the node is to the bubble, None outside of a subgraph
+    pub role: Option<NodeRole>,
+    // samples whose paths go through the node
+    pub samples: Vec<String>,
+    // rGFA stable coordinate (SN/SO/SR tags)
+    pub stable: Option<StableCoord>,
+    // all other optional fields of the segment, e.g. LN, RC, DP
+    pub tags: BTreeMap<String, TagValue>,
+    // neighbours left out of a subgraph, set where a budget cut the extraction
+    pub hidden: usize,
+}
+
+// what a node of a subgraph is to the bubble it was extracted around
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NodeRole {
+    // inner node of the REF traversal only
+    Ref,
+    // inner node of one ALT traversal only
+    Alt,
+    // inner node of several traversals
+    Shared,
+    // first or last node of the REF traversal, where the bubble opens and closes
+    Endpoint,
+    // context walked by a path through the bubble, or any context without paths
+    Flank,
+    // context that no path through the bubble walks
+    OffPath,
+    // node overlapping the queried region of stable coordinates
+    Region,
+    // context node where a node, edge or bp budget cut the expansion
+    Boundary,
+}
+
+impl Display for NodeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NodeRole::Ref => "REF",
+            NodeRole::Alt => "ALT",
+            NodeRole::Shared => "SHARED",
+            NodeRole::Endpoint => "ENDPOINT",
+            NodeRole::Flank => "FLANK",
+            NodeRole::OffPath => "OFF_PATH",
+            NodeRole::Region => "REGION",
+            NodeRole::Boundary => "BOUNDARY",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for NodeRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "REF" => Ok(NodeRole::Ref),
+            "ALT" => Ok(NodeRole::Alt),
+            "SHARED" => Ok(NodeRole::Shared),
+            "ENDPOINT" => Ok(NodeRole::Endpoint),
+            "FLANK" => Ok(NodeRole::Flank),
+            "OFF_PATH" => Ok(NodeRole::OffPath),
+            "REGION" => Ok(NodeRole::Region),
+            "BOUNDARY" => Ok(NodeRole::Boundary),
+            _ => Err(anyhow::anyhow!("Unknown node role: {}", s)),
+        }
+    }
+}
+
+// value of a segment tag, by its GFA type letter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TagValue {
+    Int(i64),
+    Float(f64),
+    // A, Z, H, J and B values are kept as text
+    Text(String),
+}
+
+impl TagValue {
+    // written as a GML value, strings are quoted and escaped
+    pub fn to_gml(&self) -> String {
+        match self {
+            TagValue::Int(value) => value.to_string(),
+            // a float keeps its decimal point, 3 would read back as an integer
+            TagValue::Float(value) if value.is_finite() && value.fract() == 0.0 => {
+                format!("{:.1}", value)
+            }
+            TagValue::Float(value) if value.is_finite() => value.to_string(),
+            TagValue::Float(value) => format!("\"{}\"", value),
+            TagValue::Text(value) => {
+                format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+        }
+    }
+
+    // written back as a GFA optional field
+    fn to_field(&self, tag: &str) -> String {
+        match self {
+            TagValue::Int(value) => format!("{}:i:{}", tag, value),
+            TagValue::Float(value) => format!("{}:f:{}", tag, value),
+            TagValue::Text(value) => format!("{}:Z:{}", tag, value),
+        }
+    }
+}
+
+// parse a TG:T:VALUE optional field
+pub fn parse_tag(field: &[u8]) -> Option<(String, TagValue)> {
+    let field = std::str::from_utf8(field).ok()?;
+    let mut parts = field.splitn(3, ':');
+    let (tag, kind, value) = (parts.next()?, parts.next()?, parts.next()?);
+    let value = match kind {
+        "i" => TagValue::Int(value.parse().ok()?),
+        "f" => TagValue::Float(value.parse().ok()?),
+        _ => TagValue::Text(value.to_string()),
+    };
+    Some((tag.to_string(), value))
+}
+
+// SN, SO and SR are kept as the stable coordinate, not as tags
+fn is_stable_tag(field: &[u8]) -> bool {
+    [&b"SN:"[..], b"SO:", b"SR:"]
+        .iter()
+        .any(|prefix| field.starts_with(prefix))
+}
+
+// typed tags of an S line, without the stable coordinate
+pub(crate) fn tags_from_fields(fields: &[&[u8]]) -> BTreeMap<String, TagValue> {
+    fields
+        .iter()
+        .filter(|field| !is_stable_tag(field))
+        .filter_map(|field| parse_tag(field))
+        .collect()
+}
+
+// sequence length of a segment, "*" sequences take it from LN
+pub(crate) fn segment_len(sequence: &[u8], tags: &[&[u8]]) -> usize {
+    if !sequence.is_empty() && sequence != b"*" {
+        return sequence.len();
+    }
+    tags.iter()
+        .find_map(|field| field.strip_prefix(b"LN:i:"))
+        .and_then(|len| std::str::from_utf8(len).ok()?.parse().ok())
+        .unwrap_or(0)
+}
+
+// per-node annotations, only stored for subgraph nodes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct NodeAttrs {
+    status: String,
+    role: Option<NodeRole>,
+    samples: Vec<String>,
+    hidden: usize,
+}
+
+// split an S line, S <name> <sequence> <tag>*
+pub(crate) fn segment_fields(line: &[u8]) -> Result<Vec<&[u8]>> {
+    let fields: Vec<&[u8]> = line.split(|&b| b == b'\t').collect();
+    if fields.len() < 3 {
+        return Err(anyhow::anyhow!(
+            "Invalid S line: {}",
+            String::from_utf8_lossy(line)
+        ));
+    }
+    Ok(fields)
+}
+
+// parse an L line, L <from> <from_orient> <to> <to_orient> <overlap>
+pub(crate) fn parse_link_line(line: &[u8]) -> Result<(&[u8], &[u8], EdgeData)> {
+    let fields: Vec<&[u8]> = line.split(|&b| b == b'\t').collect();
+    if fields.len() < 5 {
+        return Err(anyhow::anyhow!(
+            "Invalid L line: {}",
+            String::from_utf8_lossy(line)
+        ));
+    }
+    let orient = |field: &[u8]| Orientation::from_str(&String::from_utf8_lossy(field));
+    let overlap = fields
+        .get(5)
+        .map(|o| String::from_utf8_lossy(o))
+        .unwrap_or_default();
+    let edge_data = EdgeData::new(orient(fields[2])?, orient(fields[4])?, &overlap);
+    Ok((fields[1], fields[3], edge_data))
+}
+
+// read the stable coordinate from the tag fields of an S line
+pub(crate) fn stable_from_tags(tags: &[&[u8]]) -> Option<StableCoord> {
+    let tags: Vec<OptField> = tags.iter().filter_map(|tag| OptField::parse(tag)).collect();
+    stable_from_opt_fields(&tags)
+}
+
+// read SN, SO and SR from the optional fields of a segment
+fn stable_from_opt_fields(fields: &[OptField]) -> Option<StableCoord> {
+    let get = |tag: &[u8]| fields.iter().find(|f| f.tag == tag).map(|f| &f.value);
+    let name = match get(b"SN")? {
+        OptFieldVal::Z(name) => String::from_utf8_lossy(name).into_owned(),
+        _ => return None,
+    };
+    let offset = match get(b"SO")? {
+        OptFieldVal::Int(offset) => *offset as usize,
+        _ => return None,
+    };
+    let rank = match get(b"SR") {
+        Some(OptFieldVal::Int(rank)) => *rank as usize,
+        _ => 0,
+    };
+    Some(StableCoord { name, offset, rank })
+}
+
+// strand of a segment side, + or - in GFA
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Orientation {
+    #[default]
+    Forward,
+    Backward,
+}
+
+impl Orientation {
+    pub fn flip(self) -> Self {
+        match self {
+            Orientation::Forward => Orientation::Backward,
+            Orientation::Backward => Orientation::Forward,
+        }
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        *self == Orientation::Backward
+    }
+}
+
+// reverse complement of a sequence, IUPAC codes and case are kept
+pub fn reverse_complement(seq: &str) -> String {
+    seq.chars()
+        .rev()
+        .map(|base| match base {
+            'A' => 'T',
+            'C' => 'G',
+            'G' => 'C',
+            'T' | 'U' => 'A',
+            'a' => 't',
+            'c' => 'g',
+            'g' => 'c',
+            't' | 'u' => 'a',
+            'R' => 'Y',
+            'Y' => 'R',
+            'K' => 'M',
+            'M' => 'K',
+            'B' => 'V',
+            'V' => 'B',
+            'D' => 'H',
+            'H' => 'D',
+            'r' => 'y',
+            'y' => 'r',
+            'k' => 'm',
+            'm' => 'k',
+            'b' => 'v',
+            'v' => 'b',
+            'd' => 'h',
+            'h' => 'd',
+            other => other,
+        })
+        .collect()
+}
+
+impl From<gfa::gfa::Orientation> for Orientation {
+    fn from(orient: gfa::gfa::Orientation) -> Self {
+        match orient {
+            gfa::gfa::Orientation::Forward => Orientation::Forward,
+            gfa::gfa::Orientation::Backward => Orientation::Backward,
+        }
+    }
+}
+
+impl Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Orientation::Forward => write!(f, "+"),
+            Orientation::Backward => write!(f, "-"),
+        }
+    }
+}
+
+impl FromStr for Orientation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "+" => Ok(Orientation::Forward),
+            "-" => Ok(Orientation::Backward),
+            _ => Err(anyhow::anyhow!("Invalid orientation: {}", s)),
+        }
+    }
+}
+
+// overlap of a link, most graphs only use * or a plain match
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Overlap {
+    Unknown,
+    Match(u32),
+    Cigar(Box<str>),
 }
-impl NodeData {
-    fn default() -> NodeData {
-        NodeData {
-            id: Vec::new(),
-            sequence: String::new(),
-            status: String::new(),
+
+impl Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overlap::Unknown => write!(f, "*"),
+            Overlap::Match(len) => write!(f, "{}M", len),
+            Overlap::Cigar(cigar) => write!(f, "{}", cigar),
+        }
+    }
+}
+
+impl From<&str> for Overlap {
+    fn from(s: &str) -> Self {
+        if s.is_empty() || s == "*" {
+            return Overlap::Unknown;
+        }
+        match s.strip_suffix('M').map(|len| len.parse::<u32>()) {
+            Some(Ok(len)) => Overlap::Match(len),
+            _ => Overlap::Cigar(s.into()),
         }
     }
 }
 
-#[derive(Debug)]
-// GFAGraph stores the node name as String
+impl Overlap {
+    // the overlap of the same link read from the other strand, operations are reversed and
+    // insertions become deletions
+    pub fn reversed(&self) -> Self {
+        self.swap_segments(true)
+    }
+
+    // the overlap read with the two segments exchanged on the same strands, insertions
+    // become deletions and the operations keep their order
+    pub fn swapped(&self) -> Self {
+        self.swap_segments(false)
+    }
+
+    fn swap_segments(&self, reverse: bool) -> Self {
+        match self {
+            Overlap::Cigar(cigar) => {
+                let mut ops = Vec::new();
+                let mut len = String::new();
+                for c in cigar.chars() {
+                    if c.is_ascii_digit() {
+                        len.push(c);
+                        continue;
+                    }
+                    let op = match c {
+                        'I' => 'D',
+                        'D' => 'I',
+                        op => op,
+                    };
+                    ops.push(format!("{}{}", std::mem::take(&mut len), op));
+                }
+                if reverse {
+                    ops.reverse();
+                }
+                Overlap::Cigar(ops.concat().into())
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+// bidirected edge, from_orient and to_orient are the strands joined by the link
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EdgeData {
+    pub from_orient: Orientation,
+    pub to_orient: Orientation,
+    pub overlap: Overlap,
+}
+
+impl EdgeData {
+    pub fn new(from_orient: Orientation, to_orient: Orientation, overlap: &str) -> Self {
+        Self {
+            from_orient,
+            to_orient,
+            overlap: overlap.into(),
+        }
+    }
+
+    // the step reached by leaving step a through this link, stored from -> to, None if the
+    // link is not on the side a leaves through
+    pub fn step_from<T: PartialEq + Copy>(
+        &self,
+        from: T,
+        to: T,
+        a: (T, Orientation),
+    ) -> Option<(T, Orientation)> {
+        if from == a.0 && self.from_orient == a.1 {
+            Some((to, self.to_orient))
+        } else if to == a.0 && self.to_orient == a.1.flip() {
+            Some((from, self.from_orient.flip()))
+        } else {
+            None
+        }
+    }
+
+    // whether this link, stored from -> to, is walked by step a followed by step b,
+    // a+ -> b- is the same link as b+ -> a-
+    pub fn walks<T: PartialEq>(
+        &self,
+        from: T,
+        to: T,
+        a: (T, Orientation),
+        b: (T, Orientation),
+    ) -> bool {
+        (from == a.0 && to == b.0 && self.from_orient == a.1 && self.to_orient == b.1)
+            || (from == b.0
+                && to == a.0
+                && self.from_orient == b.1.flip()
+                && self.to_orient == a.1.flip())
+    }
+}
+
+impl Default for EdgeData {
+    fn default() -> Self {
+        Self::new(Orientation::Forward, Orientation::Forward, "*")
+    }
+}
+
+// read access to a graph, enough to extract and annotate subgraphs,
+// implemented by the in-memory GFAGraph and the lazily loaded LazyGraph
+pub trait GraphView {
+    // get node index by node name
+    fn get_node_idx(&self, node_id: &[u8]) -> Option<NodeIndex>;
+
+    // get all attributes of a node
+    fn get_node_data(&self, node_idx: NodeIndex) -> Option<NodeData>;
+
+    // get node id
+    fn get_id(&self, node_idx: NodeIndex) -> Option<Cow<'_, [u8]>>;
+
+    // get seq length of a node
+    fn get_seq_len(&self, node_idx: NodeIndex) -> Option<usize>;
+
+    // get incident edges of a node idx as (edge idx, from idx, to idx, edge data)
+    fn edges(&self, node_idx: NodeIndex) -> Vec<(EdgeIndex, NodeIndex, NodeIndex, EdgeData)>;
+
+    // get nodes overlapping a region of stable coordinates
+    fn nodes_in_region(&self, region: &Region) -> Vec<NodeIndex>;
+
+    // whether a contig of stable coordinates, or the contig or name of a path, is in the graph
+    fn has_contig(&self, contig: &str) -> bool;
+
+    fn paths(&self) -> &[PathData];
+
+    // false if the P and W lines could not be loaded, paths() is then empty whatever the GFA holds
+    fn paths_loaded(&self) -> bool {
+        true
+    }
+
+    // get (path id, step rank) of all path steps on a node
+    fn path_occurrences(&self, node_idx: NodeIndex) -> &[(u32, u32)];
+
+    // get seq length of a node by node id
+    fn get_seq_len_by_id(&self, node_id: &[u8]) -> Option<usize> {
+        self.get_node_idx(node_id)
+            .and_then(|idx| self.get_seq_len(idx))
+    }
+
+    // get sorted sample names whose paths go through a node
+    fn samples_through(&self, node_idx: NodeIndex) -> Vec<String> {
+        let mut samples: Vec<String> = self
+            .path_occurrences(node_idx)
+            .iter()
+            .map(|&(path_id, _)| self.paths()[path_id as usize].sample.clone())
+            .collect();
+        samples.sort();
+        samples.dedup();
+        samples
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+// GFAGraph stores every segment name and sequence once, in compact arrays
 pub struct GFAGraph {
-    // store node id
-    pub inner_graph: UnGraph<Vec<u8>, ()>,
-    // store node attrs
-    node_attrs: Vec<NodeData>,
-    // id to index map
-    id_to_idx: HashMap<Vec<u8>, NodeIndex>,
+    // topology only, node weights live in the stores below
+    pub inner_graph: UnGraph<(), EdgeData>,
+    // interned segment names, name index == node index
+    names: SegmentNames,
+    // packed or memory-mapped sequences
+    sequences: SequenceStore,
+    // raw optional fields of the S lines
+    tags: SegmentTags,
+    // status and samples of subgraph nodes, empty for a whole graph
+    node_attrs: Vec<NodeAttrs>,
+    // haplotype paths from P and W lines
+    paths: Vec<PathData>,
+    // node to path steps index
+    path_index: PathIndex,
+    // stable coordinates and their index to nodes
+    coord_index: CoordIndex,
 }
 
 impl Default for GFAGraph {
@@ -47,26 +529,99 @@ impl Default for GFAGraph {
 impl GFAGraph {
     // add node in graph
     pub fn add_node(&mut self, id: Vec<u8>, sequence: String, status: String) -> Result<NodeIndex> {
-        let node_idx = self.inner_graph.add_node(id.clone());
-        let node_data = NodeData {
-            id: id.clone(),
+        self.add_node_data(NodeData {
+            id,
             sequence,
             status,
-        };
+            ..NodeData::default()
+        })
+    }
+
+    // add node in graph with all attributes
+    pub fn add_node_data(&mut self, node_data: NodeData) -> Result<NodeIndex> {
+        let tags: Vec<String> = node_data
+            .tags
+            .iter()
+            .map(|(tag, value)| value.to_field(tag))
+            .collect();
+        let tags: Vec<&[u8]> = tags.iter().map(|tag| tag.as_bytes()).collect();
+        let node_idx = self.add_segment(&node_data.id, node_data.sequence.as_bytes(), &tags)?;
+        if let Some(stable) = &node_data.stable {
+            let len = self.get_seq_len(node_idx).unwrap_or(0);
+            self.coord_index.insert(node_idx, stable, len);
+        }
 
         while self.node_attrs.len() <= node_idx.index() {
-            self.node_attrs.push(NodeData::default());
+            self.node_attrs.push(NodeAttrs::default());
         }
-        self.node_attrs[node_idx.index()] = node_data;
-        self.id_to_idx.insert(id, node_idx);
+        self.node_attrs[node_idx.index()] = NodeAttrs {
+            status: node_data.status,
+            role: node_data.role,
+            samples: node_data.samples,
+            hidden: node_data.hidden,
+        };
 
         Ok(node_idx)
     }
 
-    // add edge in graph
-    pub fn add_edge(&mut self, from: &[u8], to: &[u8]) -> Result<()> {
+    // add a segment with the raw optional fields of its S line,
+    // the sequence is packed into the store
+    pub fn add_segment(&mut self, id: &[u8], sequence: &[u8], tags: &[&[u8]]) -> Result<NodeIndex> {
+        self.add_sized_segment(id, sequence, segment_len(sequence, tags), tags)
+    }
+
+    // add a segment whose length is given apart from its tags, as the slen of a GFA2 S line
+    pub(crate) fn add_sized_segment(
+        &mut self,
+        id: &[u8],
+        sequence: &[u8],
+        len: usize,
+        tags: &[&[u8]],
+    ) -> Result<NodeIndex> {
+        let missing = sequence.is_empty() || sequence == b"*";
+        if !missing && self.sequences.is_mapped() {
+            return Err(anyhow::anyhow!(
+                "Cannot add segment {} with a sequence to a memory-mapped graph",
+                String::from_utf8_lossy(id)
+            ));
+        }
+        self.names.insert(id)?;
+        if missing {
+            self.sequences.push_missing(len);
+        } else {
+            self.sequences.push(sequence)?;
+        }
+        self.push_tags(tags);
+        Ok(self.inner_graph.add_node(()))
+    }
+
+    // add a segment whose sequence is a slice of the memory-mapped file
+    fn add_mapped_segment(
+        &mut self,
+        id: &[u8],
+        start: u64,
+        len: usize,
+        tags: &[&[u8]],
+    ) -> Result<NodeIndex> {
+        self.names.insert(id)?;
+        self.sequences.push_mapped(start, len);
+        self.push_tags(tags);
+        Ok(self.inner_graph.add_node(()))
+    }
+
+    fn push_tags(&mut self, tags: &[&[u8]]) {
+        let tags: Vec<&[u8]> = tags
+            .iter()
+            .filter(|tag| !is_stable_tag(tag))
+            .copied()
+            .collect();
+        self.tags.push(&tags);
+    }
+
+    // add oriented edge in graph
+    pub fn add_edge(&mut self, from: &[u8], to: &[u8], data: EdgeData) -> Result<()> {
         if let (Some(from_idx), Some(to_idx)) = (self.get_node_idx(from), self.get_node_idx(to)) {
-            self.inner_graph.add_edge(from_idx, to_idx, ());
+            self.inner_graph.add_edge(from_idx, to_idx, data);
             Ok(())
         } else {
             Err(anyhow::anyhow!("Node not found"))
@@ -75,11 +630,54 @@ impl GFAGraph {
     pub fn new() -> Self {
         Self {
             inner_graph: Graph::new_undirected(),
+            names: SegmentNames::default(),
+            sequences: SequenceStore::new(),
+            tags: SegmentTags::default(),
             node_attrs: Vec::new(),
-            id_to_idx: HashMap::new(),
+            paths: Vec::new(),
+            path_index: PathIndex::default(),
+            coord_index: CoordIndex::default(),
         }
     }
 
+    // hash the segment names again after loading the graph from an index
+    pub fn rebuild_name_lookup(&mut self) {
+        self.names.rebuild_lookup();
+    }
+
+    // whether sequences are read from the memory-mapped source file
+    pub fn sequences_mapped(&self) -> bool {
+        self.sequences.is_mapped()
+    }
+
+    // map the source file again after loading the graph from an index
+    pub fn remap_sequences(&mut self, map: Mmap) {
+        self.sequences.remap(map);
+    }
+
+    // index the rGFA SN/SO/SR tags of a segment, if it has them
+    pub(crate) fn index_stable_tags(&mut self, node_idx: NodeIndex, tags: &[&[u8]]) {
+        if let Some(stable) = stable_from_tags(tags) {
+            let len = self.get_seq_len(node_idx).unwrap_or(0);
+            self.coord_index.insert(node_idx, &stable, len);
+        }
+    }
+
+    // sort the stable coordinate index after all segments are added
+    pub fn build_coord_index(&mut self) {
+        self.coord_index.finish();
+    }
+
+    // add a path, steps must already be in the graph
+    pub fn add_path(&mut self, path: PathData) {
+        self.paths.push(path);
+    }
+
+    // rebuild node to path index after all paths are added
+    pub fn build_path_index(&mut self) {
+        self.path_index = PathIndex::build(self.node_count(), &self.paths);
+    }
+
     /// Convert the graph to GML format string
     pub fn to_gml_string(&self) -> String {
         let mut result = String::new();
@@ -96,6 +694,23 @@ impl GFAGraph {
                 ));
                 result.push_str(&format!("\t\tsequence \"{}\"\n", node_data.sequence));
                 result.push_str(&format!("\t\tstatus \"{}\"\n", node_data.status));
+                if let Some(role) = node_data.role {
+                    result.push_str(&format!("\t\trole \"{}\"\n", role));
+                }
+                result.push_str(&format!(
+                    "\t\tsamples \"{}\"\n",
+                    node_data.samples.join(",")
+                ));
+                if node_data.hidden > 0 {
+                    result.push_str(&format!("\t\thidden {}\n", node_data.hidden));
+                }
+                if !node_data.tags.is_empty() {
+                    result.push_str("\t\ttags [\n");
+                    for (tag, value) in node_data.tags.iter() {
+                        result.push_str(&format!("\t\t\t{} {}\n", tag, value.to_gml()));
+                    }
+                    result.push_str("\t\t]\n");
+                }
                 result.push_str("\t]\n");
             }
         }
@@ -105,21 +720,30 @@ impl GFAGraph {
 
         // Write edges
         for edge_ref in self.inner_graph.edge_references() {
-            let source = edge_ref.source().index();
-            let target = edge_ref.target().index();
+            let data = edge_ref.weight();
+            let source = (edge_ref.source().index(), data.from_orient);
+            let target = (edge_ref.target().index(), data.to_orient);
 
-            // always store the smaller node index first
-            let (source, target) = if source < target {
-                (source, target)
+            // a+ -> b- is the same link as b+ -> a-, write it from the smaller node index,
+            // the overlap is then read from the other strand
+            let (key, overlap) = if source.0 <= target.0 {
+                ((source, target), data.overlap.clone())
             } else {
-                (target, source)
+                (
+                    ((target.0, target.1.flip()), (source.0, source.1.flip())),
+                    data.overlap.reversed(),
+                )
             };
 
             // only write edge if it hasn't been written before
-            if processed_edges.insert((source, target)) {
+            if processed_edges.insert(key) {
+                let (source, target) = key;
                 result.push_str("\tedge [\n");
-                result.push_str(&format!("\t\tsource {}\n", source));
-                result.push_str(&format!("\t\ttarget {}\n", target));
+                result.push_str(&format!("\t\tsource {}\n", source.0));
+                result.push_str(&format!("\t\ttarget {}\n", target.0));
+                result.push_str(&format!("\t\tfrom_orient \"{}\"\n", source.1));
+                result.push_str(&format!("\t\tto_orient \"{}\"\n", target.1));
+                result.push_str(&format!("\t\toverlap \"{}\"\n", overlap));
                 result.push_str("\t]\n");
             }
         }
@@ -128,22 +752,19 @@ impl GFAGraph {
         result
     }
 
-    // get node data by node name
-    pub fn get_node_data(&self, idx: NodeIndex) -> Option<&NodeData> {
-        self.node_attrs.get(idx.index())
-    }
-
-    // get node index by node name
-    pub fn get_node_idx(&self, node_id: &[u8]) -> Option<NodeIndex> {
-        self.id_to_idx.get(node_id).copied()
-    }
-
     // get node data by node id
-    pub fn get_node_data_by_id(&self, node_id: &[u8]) -> Option<&NodeData> {
+    pub fn get_node_data_by_id(&self, node_id: &[u8]) -> Option<NodeData> {
         self.get_node_idx(node_id)
             .and_then(|idx| self.get_node_data(idx))
     }
 
+    // get status of a node
+    pub fn get_status(&self, node_idx: NodeIndex) -> Option<&str> {
+        self.node_attrs
+            .get(node_idx.index())
+            .map(|attrs| attrs.status.as_str())
+    }
+
     pub fn node_count(&self) -> usize {
         self.inner_graph.node_count()
     }
@@ -164,26 +785,9 @@ impl GFAGraph {
             .unwrap_or_default()
     }
 
-    // get sequence of a node
-    pub fn get_sequence(&self, node_idx: NodeIndex) -> Option<&[u8]> {
-        self.get_node_data(node_idx)
-            .map(|data| data.sequence.as_bytes())
-    }
-
-    // get seq length of a node
-    pub fn get_seq_len(&self, node_idx: NodeIndex) -> Option<usize> {
-        self.get_node_data(node_idx).map(|data| data.sequence.len())
-    }
-
-    // get seq length of a node by node id
-    pub fn get_seq_len_by_id(&self, node_id: &[u8]) -> Option<usize> {
-        self.get_node_idx(node_id)
-            .and_then(|idx| self.get_seq_len(idx))
-    }
-
-    // get node id
-    pub fn get_id(&self, node_idx: NodeIndex) -> Option<&[u8]> {
-        self.get_node_data(node_idx).map(|data| data.id.as_slice())
+    // get sequence of a node, unpacked on demand
+    pub fn get_sequence(&self, node_idx: NodeIndex) -> Option<Cow<'_, [u8]>> {
+        self.sequences.get(node_idx.index())
     }
 
     // get all node indices
@@ -212,49 +816,292 @@ impl GFAGraph {
     }
 }
 
+impl GraphView for GFAGraph {
+    fn get_node_idx(&self, node_id: &[u8]) -> Option<NodeIndex> {
+        self.names
+            .get(node_id)
+            .map(|idx| NodeIndex::new(idx as usize))
+    }
+
+    fn get_node_data(&self, node_idx: NodeIndex) -> Option<NodeData> {
+        let id = self.get_id(node_idx)?.into_owned();
+        let sequence = String::from_utf8_lossy(&self.get_sequence(node_idx)?).into_owned();
+        let attrs = self
+            .node_attrs
+            .get(node_idx.index())
+            .cloned()
+            .unwrap_or_default();
+        Some(NodeData {
+            id,
+            sequence,
+            status: attrs.status,
+            role: attrs.role,
+            samples: attrs.samples,
+            hidden: attrs.hidden,
+            stable: self.coord_index.get(node_idx),
+            tags: self
+                .tags
+                .get(node_idx.index())
+                .map(|raw| tags_from_fields(&raw.split(|&b| b == b'\t').collect::<Vec<_>>()))
+                .unwrap_or_default(),
+        })
+    }
+
+    fn get_id(&self, node_idx: NodeIndex) -> Option<Cow<'_, [u8]>> {
+        self.names.name(node_idx.index()).map(Cow::Borrowed)
+    }
+
+    fn get_seq_len(&self, node_idx: NodeIndex) -> Option<usize> {
+        self.sequences.seq_len(node_idx.index())
+    }
+
+    fn edges(&self, node_idx: NodeIndex) -> Vec<(EdgeIndex, NodeIndex, NodeIndex, EdgeData)> {
+        self.inner_graph
+            .edges(node_idx)
+            .filter_map(|edge_ref| {
+                // undirected edges() yields node_idx as source, recover the stored direction
+                let (from, to) = self.inner_graph.edge_endpoints(edge_ref.id())?;
+                Some((edge_ref.id(), from, to, edge_ref.weight().clone()))
+            })
+            .collect()
+    }
+
+    fn nodes_in_region(&self, region: &Region) -> Vec<NodeIndex> {
+        self.coord_index.query(region)
+    }
+
+    fn has_contig(&self, contig: &str) -> bool {
+        self.coord_index.has_contig(contig)
+            || self
+                .paths
+                .iter()
+                .any(|path| path.contig == contig || path.name == contig)
+    }
+
+    fn paths(&self) -> &[PathData] {
+        &self.paths
+    }
+
+    fn path_occurrences(&self, node_idx: NodeIndex) -> &[(u32, u32)] {
+        self.path_index.occurrences(node_idx)
+    }
+}
+
+// GFA spec version, from the VN tag of the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GfaVersion {
+    Gfa1,
+    Gfa2,
+}
+
+// read the header lines to find the VN tag, GFA1 if there is none
+pub fn gfa_version(path: &str) -> Result<GfaVersion> {
+    for line in open_text(path)?.lines() {
+        let line = line?;
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if !line.starts_with('H') {
+            break;
+        }
+        for tag in line.trim_end().split('\t').skip(1) {
+            if let Some(version) = tag.strip_prefix("VN:Z:") {
+                if version.starts_with('2') {
+                    return Ok(GfaVersion::Gfa2);
+                }
+                return Ok(GfaVersion::Gfa1);
+            }
+        }
+    }
+    Ok(GfaVersion::Gfa1)
+}
+
+// how segment sequences are held in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SequenceMode {
+    // 2-bit/4-bit packed copies
+    #[default]
+    Packed,
+    // slices of the memory-mapped GFA file, nothing is copied
+    Mmap,
+}
+
 pub fn gfa_to_graph(path: &str) -> Result<GFAGraph> {
-    // parse using rs-gfa
-    let parser = GFAParser::new();
-    parser.ignore_line_type(b'H');
-    parser.ignore_line_type(b'P');
-    parser.ignore_line_type(b'C');
-    let gfa: GFA<Vec<u8>, ()> = parser.parse_file(path)?;
-
-    // build new graph
-    let mut gfa_graph = GFAGraph::new();
-
-    // add nodes
-    for segment in gfa.segments {
-        let node_id = segment.name;
-        let node_idx = gfa_graph.inner_graph.add_node(node_id.clone());
-        gfa_graph.id_to_idx.insert(node_id.clone(), node_idx);
-
-        // ensure node data vector is long enough
-        while gfa_graph.node_attrs.len() <= node_idx.index() {
-            gfa_graph.node_attrs.push(NodeData::default());
+    gfa_to_graph_with(path, SequenceMode::Packed)
+}
+
+// load a GFA1 or GFA2 text file, see source::load_graph for other formats
+pub fn gfa_to_graph_with(path: &str, mode: SequenceMode) -> Result<GFAGraph> {
+    if gfa_version(path)? == GfaVersion::Gfa2 {
+        // GFA2 sequences are always packed, a silent fallback would hide the memory cost
+        if mode == SequenceMode::Mmap {
+            return Err(anyhow::anyhow!(
+                "Cannot memory-map sequences of a GFA2 file: {}",
+                path
+            ));
         }
+        return gfa2_to_graph(path);
+    }
+
+    if mode == SequenceMode::Packed {
+        return gfa1_from_reader(open_text(path)?);
+    }
+    if compression(path)? != Compression::None {
+        return Err(anyhow::anyhow!(
+            "Cannot memory-map sequences of a compressed GFA: {}",
+            path
+        ));
+    }
+
+    // one map to read the lines, one owned by the sequence store
+    let file = File::open(path)?;
+    let lines = unsafe { Mmap::map(&file)? };
+    let mut loader = Gfa1Loader::default();
+    loader.graph.sequences = SequenceStore::mapped(unsafe { Mmap::map(&file)? });
+    let mut offset = 0;
+    for line in lines.split(|&b| b == b'\n') {
+        loader.add_line(line, Some(offset))?;
+        offset += line.len() as u64 + 1;
+    }
+    loader.finish()
+}
 
-        // update node data
-        gfa_graph.node_attrs[node_idx.index()] = NodeData {
-            id: node_id,
-            sequence: segment.sequence.display(),
-            // unnecessary for whole graph
-            status: String::new(),
+// load GFA1 lines from any reader, e.g. the output of a converter
+pub(crate) fn gfa1_from_reader<R: BufRead>(reader: R) -> Result<GFAGraph> {
+    let mut loader = Gfa1Loader::default();
+    for line in reader.split(b'\n') {
+        loader.add_line(&line?, None)?;
+    }
+    loader.finish()
+}
+
+// streams GFA1 lines into a GFAGraph, links and paths seen before
+// their segments are kept aside until the end
+#[derive(Default)]
+struct Gfa1Loader {
+    graph: GFAGraph,
+    pending_links: Vec<(Vec<u8>, Vec<u8>, EdgeData)>,
+    pending_paths: Vec<(PathData, Vec<WalkStep>)>,
+}
+
+impl Gfa1Loader {
+    // offset is the position of the line in the memory-mapped file
+    fn add_line(&mut self, line: &[u8], offset: Option<u64>) -> Result<()> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        match line.first() {
+            Some(b'S') => self.add_segment_line(line, offset),
+            Some(b'L') => self.add_link_line(line),
+            Some(b'P') => self.add_path_line(line),
+            Some(b'W') => {
+                let (path, steps) = parse_walk_line(line)?;
+                self.add_steps(path, steps);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn add_segment_line(&mut self, line: &[u8], offset: Option<u64>) -> Result<()> {
+        let fields = segment_fields(line)?;
+        let (name, sequence) = (fields[1], fields[2]);
+        let node_idx = match offset {
+            Some(offset) if sequence != b"*" => {
+                // sequence starts after "S\t<name>\t"
+                let start = offset + 3 + name.len() as u64;
+                self.graph
+                    .add_mapped_segment(name, start, sequence.len(), &fields[3..])?
+            }
+            _ => self.graph.add_segment(name, sequence, &fields[3..])?,
         };
+        self.graph.index_stable_tags(node_idx, &fields[3..]);
+        Ok(())
+    }
+
+    fn add_link_line(&mut self, line: &[u8]) -> Result<()> {
+        let (from, to, edge_data) = parse_link_line(line)?;
+        if self.graph.get_node_idx(from).is_some() && self.graph.get_node_idx(to).is_some() {
+            self.graph.add_edge(from, to, edge_data)?;
+        } else {
+            self.pending_links
+                .push((from.to_vec(), to.to_vec(), edge_data));
+        }
+        Ok(())
+    }
+
+    // P <name> <steps> <overlaps>, names are split as PanSN
+    fn add_path_line(&mut self, line: &[u8]) -> Result<()> {
+        let (path, steps) = parse_path_line(line)?;
+        self.add_steps(path, steps);
+        Ok(())
     }
 
-    // add edges
-    for link in gfa.links.iter() {
-        let from_id = &link.from_segment;
-        let to_id = &link.to_segment;
+    // resolve steps now if all segments are known, later otherwise
+    fn add_steps(&mut self, mut path: PathData, steps: Vec<WalkStep>) {
+        if steps
+            .iter()
+            .all(|(seg, _)| self.graph.get_node_idx(seg).is_some())
+        {
+            for (seg, orient) in steps {
+                if let Some(node_idx) = self.graph.get_node_idx(&seg) {
+                    path.push_step(node_idx, orient);
+                }
+            }
+            self.graph.add_path(path);
+        } else {
+            self.pending_paths.push((path, steps));
+        }
+    }
 
-        if let (Some(from_idx), Some(to_idx)) = (
-            gfa_graph.get_node_idx(from_id),
-            gfa_graph.get_node_idx(to_id),
-        ) {
-            gfa_graph.inner_graph.add_edge(from_idx, to_idx, ());
+    fn finish(mut self) -> Result<GFAGraph> {
+        // links to segments that never showed up are dropped
+        for (from, to, edge_data) in std::mem::take(&mut self.pending_links) {
+            if self.graph.get_node_idx(&from).is_some() && self.graph.get_node_idx(&to).is_some() {
+                self.graph.add_edge(&from, &to, edge_data)?;
+            }
         }
+
+        for (mut path, steps) in std::mem::take(&mut self.pending_paths) {
+            for (seg, orient) in steps {
+                let node_idx = self.graph.get_node_idx(&seg).ok_or_else(|| {
+                    anyhow::anyhow!("Path {} references unknown segment", path.name)
+                })?;
+                path.push_step(node_idx, orient);
+            }
+            self.graph.add_path(path);
+        }
+
+        self.graph.build_path_index();
+        self.graph.build_coord_index();
+        Ok(self.graph)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gml::{GMLGraph, GMLObject, GMLValue};
+    use std::str::FromStr;
 
-    Ok(gfa_graph)
+    #[test]
+    fn test_gml_edge_from_other_strand() {
+        // 2- -> 1- is written as 1+ -> 2+, the overlap read from the other strand
+        let gfa = "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tACGT\nL\t2\t-\t1\t-\t2M1I3M\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let gml = GMLGraph::from_gml(GMLObject::from_str(&g.to_gml_string()).unwrap()).unwrap();
+        let edge = &gml.edges[0];
+        assert_eq!((edge.source, edge.target), (0, 1));
+        assert_eq!(edge.get_from_orient(), "+");
+        assert_eq!(edge.get_to_orient(), "+");
+        assert_eq!(edge.get_overlap(), "3M1D2M");
+    }
+
+    #[test]
+    fn test_gml_float_tag() {
+        let gfa = "H\tVN:Z:1.0\nS\t1\tACGT\tdp:f:3\tcv:f:0.25\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let gml = GMLGraph::from_gml(GMLObject::from_str(&g.to_gml_string()).unwrap()).unwrap();
+        let tags = gml.nodes[0].get_tags();
+        assert!(tags.contains(&("dp".to_string(), GMLValue::GMLFloat(3.0))));
+        assert!(tags.contains(&("cv".to_string(), GMLValue::GMLFloat(0.25))));
+    }
 }
@@ -0,0 +1,315 @@
+use crate::gfa::{EdgeData, GFAGraph, GraphView, Orientation};
+use crate::path::PathData;
+use crate::source::open_text;
+use anyhow::Result;
+use std::io::BufRead;
+
+// a GFA2 position, the flag is true if it ends with $ (end of segment)
+type Position = (usize, bool);
+
+// an E line, positions are on the forward strand of each segment
+#[derive(Debug, Clone, PartialEq)]
+struct Gfa2Edge {
+    sid1: Vec<u8>,
+    orient1: Orientation,
+    sid2: Vec<u8>,
+    orient2: Orientation,
+    beg1: Position,
+    end1: Position,
+    beg2: Position,
+    end2: Position,
+    alignment: String,
+}
+
+impl Gfa2Edge {
+    // E <eid> <sid1:ref> <sid2:ref> <beg1> <end1> <beg2> <end2> <alignment>
+    fn parse(fields: &[&str]) -> Result<Self> {
+        if fields.len() < 9 {
+            return Err(anyhow::anyhow!("Invalid E line: {}", fields.join("\t")));
+        }
+        let (sid1, orient1) = parse_reference(fields[2])?;
+        let (sid2, orient2) = parse_reference(fields[3])?;
+        Ok(Self {
+            sid1,
+            orient1,
+            sid2,
+            orient2,
+            beg1: parse_position(fields[4])?,
+            end1: parse_position(fields[5])?,
+            beg2: parse_position(fields[6])?,
+            end2: parse_position(fields[7])?,
+            alignment: fields[8].to_string(),
+        })
+    }
+
+    // the overlapping end of a segment, given its orientation in the edge:
+    // the tail side is the end of the oriented segment, the head side its start
+    fn touches_tail(orient: Orientation, beg: Position, end: Position) -> bool {
+        match orient {
+            Orientation::Forward => end.1,
+            Orientation::Backward => beg.0 == 0,
+        }
+    }
+
+    fn touches_head(orient: Orientation, beg: Position, end: Position) -> bool {
+        match orient {
+            Orientation::Forward => beg.0 == 0,
+            Orientation::Backward => end.1,
+        }
+    }
+
+    // convert a dovetail overlap to a GFA1-like link, containments are skipped
+    fn to_link(&self) -> Option<(&[u8], &[u8], EdgeData)> {
+        let data = EdgeData::new(self.orient1, self.orient2, &self.alignment);
+        if Self::touches_tail(self.orient1, self.beg1, self.end1)
+            && Self::touches_head(self.orient2, self.beg2, self.end2)
+        {
+            Some((&self.sid1, &self.sid2, data))
+        } else if Self::touches_head(self.orient1, self.beg1, self.end1)
+            && Self::touches_tail(self.orient2, self.beg2, self.end2)
+        {
+            // sid2 comes first, the link is read the other way round with the same strands, so
+            // the alignment only trades insertions and deletions
+            let data = EdgeData {
+                from_orient: self.orient2,
+                to_orient: self.orient1,
+                overlap: data.overlap.swapped(),
+            };
+            Some((&self.sid2, &self.sid1, data))
+        } else {
+            None
+        }
+    }
+}
+
+// parse a reference like s1+ or s2-
+fn parse_reference(reference: &str) -> Result<(Vec<u8>, Orientation)> {
+    let orient = match reference.as_bytes().last() {
+        Some(b'+') => Orientation::Forward,
+        Some(b'-') => Orientation::Backward,
+        _ => return Err(anyhow::anyhow!("Invalid GFA2 reference: {}", reference)),
+    };
+    Ok((reference.as_bytes()[..reference.len() - 1].to_vec(), orient))
+}
+
+fn parse_position(pos: &str) -> Result<Position> {
+    let (num, is_end) = match pos.strip_suffix('$') {
+        Some(num) => (num, true),
+        None => (pos, false),
+    };
+    let num = num
+        .parse::<usize>()
+        .map_err(|_| anyhow::anyhow!("Invalid GFA2 position: {}", pos))?;
+    Ok((num, is_end))
+}
+
+pub fn gfa2_to_graph(path: &str) -> Result<GFAGraph> {
+    let mut gfa_graph = GFAGraph::new();
+    let mut edges = Vec::new();
+    let mut groups = Vec::new();
+
+    // segments first, edges and groups may reference segments defined later
+    for line in open_text(path)?.lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.trim_end().split('\t').collect();
+        match fields[0] {
+            // S <sid> <slen> <sequence> <tag>*
+            "S" => {
+                if fields.len() < 4 {
+                    return Err(anyhow::anyhow!("Invalid S line: {}", line));
+                }
+                // slen is the length of "*" sequences, the tags are kept as written
+                let slen = fields[2]
+                    .parse::<usize>()
+                    .map_err(|_| anyhow::anyhow!("Invalid S line length: {}", line))?;
+                let tags: Vec<&[u8]> = fields[4..].iter().map(|tag| tag.as_bytes()).collect();
+                let node_idx = gfa_graph.add_sized_segment(
+                    fields[1].as_bytes(),
+                    fields[3].as_bytes(),
+                    slen,
+                    &tags,
+                )?;
+                gfa_graph.index_stable_tags(node_idx, &tags);
+            }
+            "E" => edges.push(Gfa2Edge::parse(&fields)?),
+            // O <oid> <ref>([ ]<ref>)*
+            "O" if fields.len() >= 3 => {
+                groups.push((fields[1].to_string(), fields[2].to_string()));
+            }
+            // U groups are unordered sets and F fragments are reads, neither is a path
+            _ => {}
+        }
+    }
+
+    // add edges
+    for edge in edges.iter() {
+        if let Some((from, to, data)) = edge.to_link() {
+            if gfa_graph.get_node_idx(from).is_some() && gfa_graph.get_node_idx(to).is_some() {
+                gfa_graph.add_edge(from, to, data)?;
+            }
+        }
+    }
+
+    // ordered groups become paths, references to edges or other groups are skipped
+    for (oid, refs) in groups {
+        let mut path = PathData::from_pansn(&oid);
+        for reference in refs.split(' ') {
+            let (sid, orient) = parse_reference(reference)?;
+            if let Some(node_idx) = gfa_graph.get_node_idx(&sid) {
+                path.push_step(node_idx, orient);
+            }
+        }
+        if !path.is_empty() {
+            gfa_graph.add_path(path);
+        }
+    }
+    gfa_graph.build_path_index();
+    gfa_graph.build_coord_index();
+
+    Ok(gfa_graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(line: &str) -> Gfa2Edge {
+        let fields: Vec<&str> = line.split('\t').collect();
+        Gfa2Edge::parse(&fields).unwrap()
+    }
+
+    #[test]
+    fn test_dovetail_to_link() {
+        // L a + b - 5M
+        let e = edge("E\t*\ta+\tb-\t95\t100$\t45\t50$\t5M");
+        let (from, to, data) = e.to_link().unwrap();
+        assert_eq!((from, to), (&b"a"[..], &b"b"[..]));
+        assert_eq!(data.from_orient, Orientation::Forward);
+        assert_eq!(data.to_orient, Orientation::Backward);
+
+        // a's start overlaps b's end, so the link is b + -> a +, with b as the first segment
+        // of the alignment
+        let e = edge("E\t*\ta+\tb+\t0\t6\t45\t50$\t2M1I3M");
+        let (from, to, data) = e.to_link().unwrap();
+        assert_eq!((from, to), (&b"b"[..], &b"a"[..]));
+        assert_eq!(data.overlap.to_string(), "2M1D3M");
+    }
+
+    #[test]
+    fn test_gfa2_to_graph() {
+        let gfa = "H\tVN:Z:2.0\n\
+                   S\ta\t4\tACGT\n\
+                   S\tb\t3\t*\n\
+                   S\tc\t5\t*\tLN:i:9\n\
+                   E\te1\ta+\tb-\t2\t4$\t1\t3$\t2M\n\
+                   E\te2\ta+\tc+\t0\t1\t4\t5$\t1M\n\
+                   O\tsmp#1#chr1\tc+ a+ e1+ b-\n\
+                   U\tu1\ta c\n\
+                   F\ta\tread1+\t0\t4\t0\t4\t4M\n";
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-gfa2-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("g.gfa");
+        std::fs::write(&path, gfa).unwrap();
+        let g = crate::gfa::gfa_to_graph(path.to_str().unwrap()).unwrap();
+        // only GFA1 sequences can be read from the mapped file
+        let mapped =
+            crate::gfa::gfa_to_graph_with(path.to_str().unwrap(), crate::gfa::SequenceMode::Mmap);
+        assert!(mapped.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(g.node_count(), 3);
+        let idx = |name: &[u8]| g.get_node_idx(name).unwrap();
+        // slen gives the length of missing sequences, an explicit LN stays a plain tag
+        assert_eq!(g.get_seq_len(idx(b"b")), Some(3));
+        assert_eq!(g.get_seq_len(idx(b"c")), Some(5));
+        assert!(!g.get_node_data(idx(b"b")).unwrap().tags.contains_key("LN"));
+        assert!(g.get_node_data(idx(b"c")).unwrap().tags.contains_key("LN"));
+
+        let mut links: Vec<_> = g
+            .edges(idx(b"a"))
+            .into_iter()
+            .map(|(_, from, to, data)| {
+                let name = |idx| String::from_utf8_lossy(&g.get_id(idx).unwrap()).into_owned();
+                (
+                    name(from),
+                    data.from_orient,
+                    name(to),
+                    data.to_orient,
+                    data.overlap.to_string(),
+                )
+            })
+            .collect();
+        links.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            links,
+            [
+                (
+                    "a".to_string(),
+                    Orientation::Forward,
+                    "b".to_string(),
+                    Orientation::Backward,
+                    "2M".to_string()
+                ),
+                (
+                    "c".to_string(),
+                    Orientation::Forward,
+                    "a".to_string(),
+                    Orientation::Forward,
+                    "1M".to_string()
+                ),
+            ]
+        );
+
+        // the O group is a path without its edge reference, U and F lines add nothing
+        assert_eq!(g.paths().len(), 1);
+        let path = &g.paths()[0];
+        assert_eq!(
+            (path.sample.as_str(), path.contig.as_str()),
+            ("smp", "chr1")
+        );
+        let steps: Vec<_> = path.steps().collect();
+        assert_eq!(
+            steps,
+            [
+                (idx(b"c"), Orientation::Forward),
+                (idx(b"a"), Orientation::Forward),
+                (idx(b"b"), Orientation::Backward),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gfa2_region() {
+        let gfa = "H\tVN:Z:2.0\n\
+                   S\ta\t4\tACGT\tSN:Z:chr1\tSO:i:0\tSR:i:0\n\
+                   S\tb\t3\t*\tSN:Z:chr1\tSO:i:4\tSR:i:0\n\
+                   S\tc\t2\tGG\tSN:Z:chr1\tSO:i:4\tSR:i:1\n\
+                   E\te1\ta+\tb+\t4$\t4$\t0\t0\t0M\n";
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-gfa2-region-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("g.gfa");
+        std::fs::write(&path, gfa).unwrap();
+        let g = crate::gfa::gfa_to_graph(path.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // chr1:5-6 is 0-based 4..6, covered by b (4..7) and the rank 1 segment c (4..6)
+        let names = |region: &str| {
+            let mut names: Vec<_> = g
+                .nodes_in_region(&region.parse().unwrap())
+                .into_iter()
+                .map(|idx| g.get_id(idx).unwrap().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names("chr1:5-6"), [b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(names("chr1:1-2"), [b"a".to_vec()]);
+        assert!(g.has_contig("chr1"));
+    }
+
+    #[test]
+    fn test_containment_skipped() {
+        let e = edge("E\t*\ta+\tb+\t10\t20\t0\t10$\t10M");
+        assert!(e.to_link().is_none());
+    }
+}
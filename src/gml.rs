@@ -6,6 +6,8 @@ use anyhow::Result;
 use pest::{iterators::Pairs, Parser};
 use pest_derive::Parser;
 
+use crate::gfa::NodeRole;
+
 #[derive(Debug)]
 pub struct GMLError(String);
 
@@ -338,6 +340,38 @@ impl Node {
             _ => "".to_string(),
         }
     }
+
+    pub fn get_role(&self) -> Option<NodeRole> {
+        match self.get_attribute("role") {
+            Some((_, GMLValue::GMLString(role))) => NodeRole::from_str(role).ok(),
+            _ => None,
+        }
+    }
+
+    // neighbours left out of the subgraph, 0 if not written
+    pub fn get_hidden(&self) -> usize {
+        match self.get_attribute("hidden") {
+            Some((_, GMLValue::GMLInt(hidden))) => *hidden as usize,
+            _ => 0,
+        }
+    }
+
+    // segment tags, written as a nested object
+    pub fn get_tags(&self) -> Vec<(String, GMLValue)> {
+        match self.get_attribute("tags") {
+            Some((_, GMLValue::GMLObject(tags))) => tags.pairs.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn get_samples(&self) -> Vec<String> {
+        match self.get_attribute("samples") {
+            Some((_, GMLValue::GMLString(samples))) if !samples.is_empty() => {
+                samples.split(',').map(|s| s.to_string()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
 }
 impl Edge {
     fn from_gml(mut obj: GMLObject) -> Result<Self, GMLError> {
@@ -381,6 +415,27 @@ impl Edge {
             attrs: obj.pairs,
         })
     }
+
+    pub fn get_from_orient(&self) -> String {
+        match self.get_attribute("from_orient") {
+            Some((_, GMLValue::GMLString(orient))) => orient.to_string(),
+            _ => "+".to_string(),
+        }
+    }
+
+    pub fn get_to_orient(&self) -> String {
+        match self.get_attribute("to_orient") {
+            Some((_, GMLValue::GMLString(orient))) => orient.to_string(),
+            _ => "+".to_string(),
+        }
+    }
+
+    pub fn get_overlap(&self) -> String {
+        match self.get_attribute("overlap") {
+            Some((_, GMLValue::GMLString(overlap))) => overlap.to_string(),
+            _ => "*".to_string(),
+        }
+    }
 }
 pub trait HasGMLAttributes {
     fn attributes(&self) -> &Vec<(String, GMLValue)>;
@@ -493,4 +548,30 @@ mod tests {
         let root = GMLObject::parse(file.into_inner()).unwrap();
         assert!(GMLGraph::from_gml(root).is_err());
     }
+
+    #[test]
+    fn parse_node_tags() {
+        let text = "graph [\n\tnode [\n\t\tid 0\n\t\tlabel \"s1\"\n\t\ttags [\n\t\t\tLN 100\n\t\t\tcons 0.5\n\t\t\tlineage \"B.1\"\n\t\t]\n\t]\n]\n";
+        let graph = GMLGraph::from_gml(GMLObject::from_str(text).unwrap()).unwrap();
+        let tags = graph.nodes[0].get_tags();
+        assert_eq!(tags[0], ("LN".to_string(), GMLValue::GMLInt(100)));
+        assert_eq!(tags[1], ("cons".to_string(), GMLValue::GMLFloat(0.5)));
+        assert_eq!(
+            tags[2],
+            (
+                "lineage".to_string(),
+                GMLValue::GMLString("B.1".to_string())
+            )
+        );
+        assert_eq!(graph.nodes[0].get_role(), None);
+        assert_eq!(graph.nodes[0].get_hidden(), 0);
+    }
+
+    #[test]
+    fn parse_node_role() {
+        let text = "graph [\n\tnode [\n\t\tid 0\n\t\tlabel \"s1\"\n\t\tstatus \"OFF_PATH\"\n\t\trole \"OFF_PATH\"\n\t\thidden 3\n\t]\n]\n";
+        let graph = GMLGraph::from_gml(GMLObject::from_str(text).unwrap()).unwrap();
+        assert_eq!(graph.nodes[0].get_role(), Some(NodeRole::OffPath));
+        assert_eq!(graph.nodes[0].get_hidden(), 3);
+    }
 }
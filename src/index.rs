@@ -0,0 +1,258 @@
+// src/index.rs
+use anyhow::Result;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::gfa::{GFAGraph, SequenceMode};
+use crate::source::load_graph;
+
+pub(crate) const INDEX_MAGIC: &[u8; 8] = b"FM3GFAIX";
+// bump whenever the layout of GFAGraph changes in a release
+const INDEX_VERSION: u32 = 1;
+// blocks of the source GFA hashed on every load, spread evenly over the file
+const SAMPLE_BLOCKS: u64 = 16;
+const SAMPLE_BLOCK_SIZE: u64 = 1 << 16;
+
+// the source GFA an index was built from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SourceInfo {
+    path: PathBuf,
+    size: u64,
+    // modification time in nanoseconds since the epoch
+    mtime: u128,
+    // crc32 of the sampled blocks
+    sample: u32,
+    checksum: u32,
+}
+
+impl SourceInfo {
+    fn from_file(path: &Path) -> Result<Self> {
+        let mut info = Self::stat(path)?;
+        info.checksum = Self::checksum(path)?;
+        Ok(info)
+    }
+
+    // size, mtime and the sampled checksum, cheap enough to check on every load
+    pub(crate) fn stat(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos();
+        Ok(Self {
+            path: fs::canonicalize(path)?,
+            size: metadata.len(),
+            mtime,
+            sample: Self::sample(path, metadata.len())?,
+            checksum: 0,
+        })
+    }
+
+    // whether two stats of a source look like the same file
+    pub(crate) fn same_stamp(&self, other: &Self) -> bool {
+        (self.size, self.mtime, self.sample) == (other.size, other.mtime, other.sample)
+    }
+
+    // crc32 of the sampled blocks, of the whole file if it is not larger than them
+    fn sample(path: &Path, size: u64) -> Result<u32> {
+        if size <= SAMPLE_BLOCKS * SAMPLE_BLOCK_SIZE {
+            return Self::checksum(path);
+        }
+        let mut hasher = crc32fast::Hasher::new();
+        let mut file = File::open(path)?;
+        let mut buf = vec![0; SAMPLE_BLOCK_SIZE as usize];
+        for block in 0..SAMPLE_BLOCKS {
+            let offset = (size - SAMPLE_BLOCK_SIZE) * block / (SAMPLE_BLOCKS - 1);
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut buf)?;
+            hasher.update(&buf);
+        }
+        Ok(hasher.finalize())
+    }
+
+    // crc32 of the whole file
+    fn checksum(path: &Path) -> Result<u32> {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut reader = BufReader::with_capacity(1 << 20, File::open(path)?);
+        let mut buf = vec![0; 1 << 20];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        Ok(hasher.finalize())
+    }
+}
+
+// an index starts with the magic bytes
+pub fn is_index(path: &str) -> Result<bool> {
+    let mut magic = [0; 8];
+    let mut file = File::open(path)?;
+    Ok(file.read_exact(&mut magic).is_ok() && &magic == INDEX_MAGIC)
+}
+
+// write a binary snapshot of a GFA graph
+pub fn write_index(gfa_path: &str, index_path: &str, mode: SequenceMode) -> Result<()> {
+    let source = SourceInfo::from_file(Path::new(gfa_path))?;
+    let graph = load_graph(gfa_path, mode)?;
+
+    let mut writer = BufWriter::new(File::create(index_path)?);
+    writer.write_all(INDEX_MAGIC)?;
+    bincode::serialize_into(&mut writer, &INDEX_VERSION)?;
+    bincode::serialize_into(&mut writer, &source)?;
+    bincode::serialize_into(&mut writer, &graph)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// read the header of an index, refusing it if the size, mtime or sampled checksum of the
+// source GFA changed
+fn open_index(index_path: &str) -> Result<(BufReader<File>, SourceInfo)> {
+    let mut reader = BufReader::new(File::open(index_path)?);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != INDEX_MAGIC {
+        return Err(anyhow::anyhow!("Not a fm3-gfa index: {}", index_path));
+    }
+
+    let version: u32 = bincode::deserialize_from(&mut reader)?;
+    if version != INDEX_VERSION {
+        return Err(anyhow::anyhow!(
+            "Index {} has version {}, expected {}, please rebuild it",
+            index_path,
+            version,
+            INDEX_VERSION
+        ));
+    }
+
+    let source: SourceInfo = bincode::deserialize_from(&mut reader)?;
+    let current = SourceInfo::stat(&source.path).map_err(|e| {
+        anyhow::anyhow!(
+            "Cannot check source {} of index {}: {}",
+            source.path.display(),
+            index_path,
+            e
+        )
+    })?;
+    if !current.same_stamp(&source) {
+        return Err(anyhow::anyhow!(
+            "Index {} is stale, {} changed since it was built, please rebuild it",
+            index_path,
+            source.path.display()
+        ));
+    }
+    Ok((reader, source))
+}
+
+// load a snapshot, the source GFA is checked by size, mtime and sampled blocks, see
+// verify_index
+pub fn load_index(index_path: &str) -> Result<GFAGraph> {
+    let (mut reader, source) = open_index(index_path)?;
+    let mut graph: GFAGraph = bincode::deserialize_from(&mut reader)?;
+    graph.rebuild_name_lookup();
+    if graph.sequences_mapped() {
+        let file = File::open(&source.path)?;
+        graph.remap_sequences(unsafe { Mmap::map(&file)? });
+    }
+    Ok(graph)
+}
+
+// compare the full checksum of the source GFA with the one recorded in the index
+pub fn verify_index(index_path: &str) -> Result<()> {
+    let (_, source) = open_index(index_path)?;
+    if SourceInfo::checksum(&source.path)? != source.checksum {
+        return Err(anyhow::anyhow!(
+            "Index {} is stale, the checksum of {} changed since it was built, please rebuild it",
+            index_path,
+            source.path.display()
+        ));
+    }
+    Ok(())
+}
+
+pub fn index(gfa_path: &str, output: Option<&str>, mode: SequenceMode) -> Result<()> {
+    let index_path = match output {
+        Some(output) => output.to_string(),
+        None => format!("{}.fm3i", gfa_path),
+    };
+    write_index(gfa_path, &index_path, mode)?;
+    eprintln!("Index written to {}", index_path);
+    Ok(())
+}
+
+pub fn verify(index_path: &str) -> Result<()> {
+    verify_index(index_path)?;
+    eprintln!("Index {} matches its source", index_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfa::GraphView;
+
+    #[test]
+    fn test_index_roundtrip_and_stale() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-index-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let gfa_path = dir.join("t.gfa");
+        let index_path = dir.join("t.gfa.fm3i");
+        let (gfa_path, index_path) = (gfa_path.to_str().unwrap(), index_path.to_str().unwrap());
+        fs::write(
+            gfa_path,
+            "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nL\t1\t+\t2\t-\t0M\nP\tHG1#1#chr1\t1+,2-\t*\n",
+        )
+        .unwrap();
+
+        for mode in [SequenceMode::Packed, SequenceMode::Mmap] {
+            write_index(gfa_path, index_path, mode).unwrap();
+            assert!(is_index(index_path).unwrap());
+            assert!(!is_index(gfa_path).unwrap());
+
+            let graph = load_graph(index_path, SequenceMode::Packed).unwrap();
+            assert_eq!(graph.node_count(), 2);
+            assert_eq!(graph.edge_count(), 1);
+            assert_eq!(graph.paths().len(), 1);
+            let idx = graph.get_node_idx(b"1").unwrap();
+            assert_eq!(graph.get_sequence(idx).unwrap().as_ref(), b"ACGT");
+        }
+
+        // same size and mtime, the sampled checksum sees the change
+        let mtime = fs::metadata(gfa_path).unwrap().modified().unwrap();
+        let gfa = fs::read_to_string(gfa_path)
+            .unwrap()
+            .replace("ACGT", "ACGA");
+        fs::write(gfa_path, gfa).unwrap();
+        let file = File::options().write(true).open(gfa_path).unwrap();
+        file.set_modified(mtime).unwrap();
+        assert!(load_index(index_path).is_err());
+        assert!(verify_index(index_path).is_err());
+
+        fs::write(gfa_path, "H\tVN:Z:1.0\nS\t1\tACGA\nS\t2\tA\n").unwrap();
+        assert!(load_index(index_path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_older_version_rejected() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-version-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let index_path = dir.join("old.fm3i");
+        let index_path = index_path.to_str().unwrap();
+        let mut old = INDEX_MAGIC.to_vec();
+        old.extend(bincode::serialize(&(INDEX_VERSION - 1)).unwrap());
+        fs::write(index_path, old).unwrap();
+
+        assert!(is_index(index_path).unwrap());
+        let error = load_index(index_path).unwrap_err().to_string();
+        assert!(
+            error.contains(&format!("has version {}", INDEX_VERSION - 1)),
+            "{}",
+            error
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,578 @@
+// src/lazy.rs
+use anyhow::Result;
+use lru::LruCache;
+use memmap2::Mmap;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+
+use crate::gfa::{
+    parse_link_line, segment_fields, segment_len, stable_from_tags, tags_from_fields, EdgeData,
+    GraphView, NodeData, TagValue,
+};
+use crate::index::SourceInfo;
+use crate::path::{parse_path_line, parse_walk_line, PathData, PathIndex};
+use crate::region::{CoordIndex, Region};
+use crate::source::{compression, detect_format, Compression, GraphFormat};
+use crate::store::SegmentNames;
+
+const LINE_INDEX_MAGIC: &[u8; 8] = b"FM3GFALZ";
+// bump whenever the layout of LineIndex changes in a release
+const LINE_INDEX_VERSION: u32 = 1;
+
+// a segment and its links, parsed from the file when first visited
+#[derive(Debug)]
+struct LoadedSegment {
+    sequence: Vec<u8>,
+    tags: BTreeMap<String, TagValue>,
+    edges: Vec<(EdgeIndex, NodeIndex, NodeIndex, EdgeData)>,
+}
+
+// paths of a lazy graph, parsed from the P and W lines when first asked for
+#[derive(Debug)]
+struct LoadedPaths {
+    paths: Vec<PathData>,
+    index: PathIndex,
+}
+
+// byte offsets of the S, L, P and W lines of a GFA1 file, saved next to it as <gfa>.fm3l
+#[derive(Debug, Serialize, Deserialize)]
+struct LineIndex {
+    names: SegmentNames,
+    // offset of the S line of every segment
+    segment_offsets: Vec<u64>,
+    seq_lens: Vec<u32>,
+    // offset of every L line, the line number among L lines is the edge index
+    link_offsets: Vec<u64>,
+    // links of segment i are link_ids[link_starts[i]..link_starts[i + 1]]
+    link_starts: Vec<u64>,
+    link_ids: Vec<u32>,
+    // offset of every P and W line, in file order
+    path_offsets: Vec<u64>,
+    coord_index: CoordIndex,
+}
+
+impl LineIndex {
+    // the saved offsets if the GFA is unchanged since they were written, a new scan otherwise
+    fn load_or_scan(path: &str, map: &[u8]) -> Result<Self> {
+        let index_path = format!("{}.fm3l", path);
+        let source = SourceInfo::stat(Path::new(path))?;
+        if let Some(lines) = Self::load(&index_path, &source) {
+            return Ok(lines);
+        }
+
+        eprintln!("Building line offset index {}", index_path);
+        let lines = Self::scan(map)?;
+        // a read-only directory only costs the next start another scan
+        if let Err(e) = lines.save(&index_path, &source) {
+            eprintln!("Cannot write {}: {}", index_path, e);
+        }
+        Ok(lines)
+    }
+
+    // None if the file is missing, unreadable, of another version or stale
+    fn load(index_path: &str, source: &SourceInfo) -> Option<Self> {
+        let mut reader = BufReader::new(File::open(index_path).ok()?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).ok()?;
+        let version: u32 = bincode::deserialize_from(&mut reader).ok()?;
+        if &magic != LINE_INDEX_MAGIC || version != LINE_INDEX_VERSION {
+            return None;
+        }
+        let saved: SourceInfo = bincode::deserialize_from(&mut reader).ok()?;
+        if !saved.same_stamp(source) {
+            return None;
+        }
+        let mut lines: Self = bincode::deserialize_from(&mut reader).ok()?;
+        lines.names.rebuild_lookup();
+        Some(lines)
+    }
+
+    fn save(&self, index_path: &str, source: &SourceInfo) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(index_path)?);
+        writer.write_all(LINE_INDEX_MAGIC)?;
+        bincode::serialize_into(&mut writer, &LINE_INDEX_VERSION)?;
+        bincode::serialize_into(&mut writer, source)?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // one pass over the file for the line offsets, then the links are grouped by segment
+    fn scan(map: &[u8]) -> Result<Self> {
+        let mut names = SegmentNames::default();
+        let mut segment_offsets = Vec::new();
+        let mut seq_lens = Vec::new();
+        let mut link_offsets = Vec::new();
+        let mut path_offsets = Vec::new();
+        let mut coord_index = CoordIndex::default();
+
+        let mut offset = 0;
+        for line in map.split(|&b| b == b'\n') {
+            match line.first() {
+                Some(b'S') => {
+                    let fields = segment_fields(trim_line(line))?;
+                    let node_idx = NodeIndex::new(names.insert(fields[1])? as usize);
+                    let len = segment_len(fields[2], &fields[3..]);
+                    segment_offsets.push(offset);
+                    seq_lens.push(len as u32);
+                    if let Some(stable) = stable_from_tags(&fields[3..]) {
+                        coord_index.insert(node_idx, &stable, len);
+                    }
+                }
+                Some(b'L') => link_offsets.push(offset),
+                Some(b'P') | Some(b'W') => path_offsets.push(offset),
+                _ => {}
+            }
+            offset += line.len() as u64 + 1;
+        }
+        coord_index.finish();
+
+        // resolve link ends to segments, links to unknown segments are dropped
+        let mut ends = Vec::with_capacity(link_offsets.len());
+        let mut degrees = vec![0u64; names.len() + 1];
+        for &offset in link_offsets.iter() {
+            let (from, to, _) = parse_link_line(line_at(map, offset))?;
+            let end = names.get(from).zip(names.get(to));
+            if let Some((from, to)) = end {
+                degrees[from as usize] += 1;
+                if from != to {
+                    degrees[to as usize] += 1;
+                }
+            }
+            ends.push(end);
+        }
+
+        let mut link_starts = Vec::with_capacity(degrees.len());
+        let mut total = 0;
+        for degree in degrees.iter() {
+            link_starts.push(total);
+            total += degree;
+        }
+        let mut fill = link_starts.clone();
+        let mut link_ids = vec![0u32; total as usize];
+        for (link_id, end) in ends.into_iter().enumerate() {
+            if let Some((from, to)) = end {
+                for node in [from, to].into_iter().take(if from == to { 1 } else { 2 }) {
+                    link_ids[fill[node as usize] as usize] = link_id as u32;
+                    fill[node as usize] += 1;
+                }
+            }
+        }
+
+        Ok(Self {
+            names,
+            segment_offsets,
+            seq_lens,
+            link_offsets,
+            link_starts,
+            link_ids,
+            path_offsets,
+            coord_index,
+        })
+    }
+}
+
+// GFA1 graph kept on disk, only byte offsets of S, L, P and W lines are held in memory,
+// segments are parsed on demand and kept in an LRU cache.
+// all paths are parsed together the first time paths or path occurrences are needed,
+// if that fails paths_loaded is false and nodes carry no samples.
+#[derive(Debug)]
+pub struct LazyGraph {
+    map: Mmap,
+    lines: LineIndex,
+    paths: OnceLock<Option<LoadedPaths>>,
+    cache: Mutex<LruCache<NodeIndex, Arc<LoadedSegment>>>,
+}
+
+impl LazyGraph {
+    // read the line offsets saved next to a GFA1 file, or scan the file for them once,
+    // cache_size is the number of segments kept parsed
+    pub fn open(path: &str, cache_size: usize) -> Result<Self> {
+        if detect_format(path)? != GraphFormat::Gfa1 || compression(path)? != Compression::None {
+            return Err(anyhow::anyhow!(
+                "Lazy loading needs an uncompressed GFA1 text file: {}",
+                path
+            ));
+        }
+        let cache_size = NonZeroUsize::new(cache_size)
+            .ok_or_else(|| anyhow::anyhow!("Cache size must be positive"))?;
+        let file = File::open(path)?;
+        let map = unsafe { Mmap::map(&file)? };
+        let lines = LineIndex::load_or_scan(path, &map)?;
+        Ok(Self {
+            map,
+            lines,
+            paths: OnceLock::new(),
+            cache: Mutex::new(LruCache::new(cache_size)),
+        })
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.lines.segment_offsets.len()
+    }
+
+    // parse the paths now instead of on the first layout, an error tells why they are missing
+    pub fn load_paths(&self) -> Result<()> {
+        if self.paths.get().is_none() {
+            let loaded = self.parse_paths()?;
+            let _ = self.paths.set(Some(loaded));
+        }
+        Ok(())
+    }
+
+    fn loaded_paths(&self) -> Option<&LoadedPaths> {
+        self.paths
+            .get_or_init(|| match self.parse_paths() {
+                Ok(loaded) => Some(loaded),
+                Err(e) => {
+                    eprintln!("Paths are not loaded: {}", e);
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    fn parse_paths(&self) -> Result<LoadedPaths> {
+        let mut paths = Vec::with_capacity(self.lines.path_offsets.len());
+        for &offset in self.lines.path_offsets.iter() {
+            let line = line_at(&self.map, offset);
+            let (mut path, steps) = match line.first() {
+                Some(b'P') => parse_path_line(line)?,
+                _ => parse_walk_line(line)?,
+            };
+            for (seg, orient) in steps {
+                let node_idx = self.get_node_idx(&seg).ok_or_else(|| {
+                    anyhow::anyhow!("Path {} references unknown segment", path.name)
+                })?;
+                path.push_step(node_idx, orient);
+            }
+            paths.push(path);
+        }
+        let index = PathIndex::build(self.node_count(), &paths);
+        Ok(LoadedPaths { paths, index })
+    }
+
+    fn cache(&self) -> MutexGuard<'_, LruCache<NodeIndex, Arc<LoadedSegment>>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // get a parsed segment, from the cache if it was visited recently
+    fn segment(&self, node_idx: NodeIndex) -> Option<Arc<LoadedSegment>> {
+        // a panic while holding the lock leaves the cache consistent, it only holds parsed lines
+        if let Some(segment) = self.cache().get(&node_idx) {
+            return Some(segment.clone());
+        }
+        let segment = Arc::new(self.load_segment(node_idx)?);
+        self.cache().put(node_idx, segment.clone());
+        Some(segment)
+    }
+
+    fn load_segment(&self, node_idx: NodeIndex) -> Option<LoadedSegment> {
+        let offset = *self.lines.segment_offsets.get(node_idx.index())?;
+        let fields = segment_fields(line_at(&self.map, offset)).ok()?;
+        let sequence = if fields[2] == b"*" {
+            Vec::new()
+        } else {
+            fields[2].to_vec()
+        };
+
+        let start = self.lines.link_starts[node_idx.index()] as usize;
+        let end = self.lines.link_starts[node_idx.index() + 1] as usize;
+        let mut edges = Vec::with_capacity(end - start);
+        for &link_id in &self.lines.link_ids[start..end] {
+            let line = line_at(&self.map, self.lines.link_offsets[link_id as usize]);
+            let (from, to, edge_data) = parse_link_line(line).ok()?;
+            let from = NodeIndex::new(self.lines.names.get(from)? as usize);
+            let to = NodeIndex::new(self.lines.names.get(to)? as usize);
+            edges.push((EdgeIndex::new(link_id as usize), from, to, edge_data));
+        }
+        Some(LoadedSegment {
+            sequence,
+            tags: tags_from_fields(&fields[3..]),
+            edges,
+        })
+    }
+}
+
+// the line starting at a byte offset, without its line break
+fn line_at(map: &[u8], offset: u64) -> &[u8] {
+    let rest = &map[offset as usize..];
+    let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+    trim_line(&rest[..end])
+}
+
+fn trim_line(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+impl GraphView for LazyGraph {
+    fn get_node_idx(&self, node_id: &[u8]) -> Option<NodeIndex> {
+        self.lines
+            .names
+            .get(node_id)
+            .map(|idx| NodeIndex::new(idx as usize))
+    }
+
+    fn get_node_data(&self, node_idx: NodeIndex) -> Option<NodeData> {
+        let segment = self.segment(node_idx)?;
+        Some(NodeData {
+            id: self.get_id(node_idx)?.into_owned(),
+            sequence: String::from_utf8_lossy(&segment.sequence).into_owned(),
+            stable: self.lines.coord_index.get(node_idx),
+            tags: segment.tags.clone(),
+            ..NodeData::default()
+        })
+    }
+
+    fn get_id(&self, node_idx: NodeIndex) -> Option<Cow<'_, [u8]>> {
+        self.lines.names.name(node_idx.index()).map(Cow::Borrowed)
+    }
+
+    fn get_seq_len(&self, node_idx: NodeIndex) -> Option<usize> {
+        self.lines
+            .seq_lens
+            .get(node_idx.index())
+            .map(|&len| len as usize)
+    }
+
+    fn edges(&self, node_idx: NodeIndex) -> Vec<(EdgeIndex, NodeIndex, NodeIndex, EdgeData)> {
+        self.segment(node_idx)
+            .map(|segment| segment.edges.clone())
+            .unwrap_or_default()
+    }
+
+    fn nodes_in_region(&self, region: &Region) -> Vec<NodeIndex> {
+        self.lines.coord_index.query(region)
+    }
+
+    // same as the in-memory graph, contigs and names of paths count too
+    fn has_contig(&self, contig: &str) -> bool {
+        self.lines.coord_index.has_contig(contig)
+            || self
+                .paths()
+                .iter()
+                .any(|path| path.contig == contig || path.name == contig)
+    }
+
+    fn paths(&self) -> &[PathData] {
+        self.loaded_paths()
+            .map(|loaded| loaded.paths.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn paths_loaded(&self) -> bool {
+        self.loaded_paths().is_some()
+    }
+
+    fn path_occurrences(&self, node_idx: NodeIndex) -> &[(u32, u32)] {
+        self.loaded_paths()
+            .map(|loaded| loaded.index.occurrences(node_idx))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bfs::{extract_subgraph_by_bfs_with, ExtractOptions, WalkMode};
+    use crate::gfa::gfa_to_graph;
+    use crate::vcf::{BubbleVariant, Traversal};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_lazy_matches_in_memory() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-lazy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.gfa");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "H\tVN:Z:1.0\nL\t1\t+\t2\t+\t0M\nS\t1\tACGT\tSN:Z:chr1\tSO:i:0\tSR:i:0\n\
+             S\t2\tA\r\nS\t3\t*\nL\t1\t+\t3\t-\t*\nL\t2\t+\t2\t-\t*\nL\t3\t+\t9\t+\t*\n",
+        )
+        .unwrap();
+
+        let lazy = LazyGraph::open(path, 1).unwrap();
+        let graph = gfa_to_graph(path).unwrap();
+        assert_eq!(lazy.node_count(), 3);
+        for id in [&b"1"[..], b"2", b"3"] {
+            let (l, g) = (
+                lazy.get_node_idx(id).unwrap(),
+                graph.get_node_idx(id).unwrap(),
+            );
+            assert_eq!(lazy.get_node_data(l), graph.get_node_data(g));
+            let mut lazy_edges = lazy.edges(l);
+            let mut edges = graph.edges(g);
+            lazy_edges.sort_by_key(|e| (e.1, e.2));
+            edges.sort_by_key(|e| (e.1, e.2));
+            let strip = |edges: Vec<(EdgeIndex, NodeIndex, NodeIndex, EdgeData)>| {
+                edges
+                    .into_iter()
+                    .map(|(_, from, to, data)| (from, to, data))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(strip(lazy_edges), strip(edges));
+        }
+        assert_eq!(
+            lazy.nodes_in_region(&"chr1:1-2".parse().unwrap()),
+            vec![NodeIndex::new(0)]
+        );
+
+        // a graph without P and W lines has no paths, that is not a loading failure
+        assert!(lazy.paths_loaded());
+        assert!(lazy.paths().is_empty());
+        let ats = [">1>2", ">1<3"].map(|at| Traversal::from_str(at).unwrap());
+        let bubble = BubbleVariant::new("v1".to_string(), 1, ats.to_vec()).unwrap();
+        assert!(
+            extract_subgraph_by_bfs_with(&bubble, &[], &ExtractOptions::default(), &lazy).is_ok()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lazy_paths() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-lazy-paths-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.gfa");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nS\t3\tC\nS\t4\tGG\n\
+             L\t1\t+\t2\t+\t0M\nL\t1\t+\t3\t+\t0M\nL\t2\t+\t4\t+\t0M\nL\t3\t+\t4\t+\t0M\n\
+             P\tGRCh38#0#chr1\t1+,2+,4+\t*\nW\tHG002\t1\tchr1\t0\t7\t>1>3>4\n",
+        )
+        .unwrap();
+
+        // the paths are parsed on first use and match the in-memory graph
+        let lazy = LazyGraph::open(path, 2).unwrap();
+        let graph = gfa_to_graph(path).unwrap();
+        assert!(lazy.paths_loaded());
+        assert_eq!(lazy.paths().len(), 2);
+        for (l, g) in lazy.paths().iter().zip(graph.paths()) {
+            assert_eq!(
+                (&l.name, &l.sample, &l.contig),
+                (&g.name, &g.sample, &g.contig)
+            );
+            assert_eq!(l.steps().collect::<Vec<_>>(), g.steps().collect::<Vec<_>>());
+        }
+        for id in [&b"1"[..], b"2", b"3", b"4"] {
+            let (l, g) = (
+                lazy.get_node_idx(id).unwrap(),
+                graph.get_node_idx(id).unwrap(),
+            );
+            assert_eq!(lazy.path_occurrences(l), graph.path_occurrences(g));
+            assert_eq!(lazy.samples_through(l), graph.samples_through(g));
+        }
+
+        // so reference walks work in lazy mode
+        let ats = [">1>2>4", ">1>3>4"].map(|at| Traversal::from_str(at).unwrap());
+        let bubble = BubbleVariant::new("v1".to_string(), 1, ats.to_vec()).unwrap();
+        let reference = ExtractOptions {
+            walk: WalkMode::Reference,
+            reference: Some("GRCh38".to_string()),
+            ..Default::default()
+        };
+        assert!(extract_subgraph_by_bfs_with(&bubble, &[], &reference, &lazy).is_ok());
+
+        // a path through an unknown segment leaves the graph without paths, reported as such
+        let path = dir.join("broken.gfa");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nL\t1\t+\t2\t+\t0M\nP\tGRCh38#0#chr1\t1+,9+\t*\n",
+        )
+        .unwrap();
+        let broken = LazyGraph::open(path, 2).unwrap();
+        assert!(broken.load_paths().is_err());
+        assert!(!broken.paths_loaded());
+        assert!(broken.paths().is_empty());
+        assert!(extract_subgraph_by_bfs_with(&bubble, &[], &reference, &broken).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_saved_line_index() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-lazy-saved-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.gfa");
+        let path = path.to_str().unwrap();
+        let index_path = format!("{}.fm3l", path);
+        std::fs::write(
+            path,
+            "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nL\t1\t+\t2\t+\t0M\nP\tp\t1+,2+\t*\n",
+        )
+        .unwrap();
+
+        // the first open writes the offsets next to the GFA, the next one reads them back
+        let first = LazyGraph::open(path, 2).unwrap();
+        let source = SourceInfo::stat(Path::new(path)).unwrap();
+        let saved = LineIndex::load(&index_path, &source).unwrap();
+        assert_eq!(saved.segment_offsets, first.lines.segment_offsets);
+        assert_eq!(saved.path_offsets, first.lines.path_offsets);
+        let second = LazyGraph::open(path, 2).unwrap();
+        let idx = second.get_node_idx(b"2").unwrap();
+        assert_eq!(second.get_node_data(idx).unwrap().sequence, "A");
+        assert_eq!(second.edges(idx).len(), 1);
+        assert_eq!(second.paths().len(), 1);
+        drop((first, second));
+
+        // a changed GFA makes the saved offsets stale, they are scanned again
+        std::fs::write(
+            path,
+            "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nS\t3\tCC\nL\t1\t+\t2\t+\t0M\n",
+        )
+        .unwrap();
+        let source = SourceInfo::stat(Path::new(path)).unwrap();
+        assert!(LineIndex::load(&index_path, &source).is_none());
+        let changed = LazyGraph::open(path, 2).unwrap();
+        assert_eq!(changed.node_count(), 3);
+        assert!(changed.paths().is_empty());
+        assert!(LineIndex::load(&index_path, &source).is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lazy_pansn_contigs() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-lazy-pansn-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.gfa");
+        let path = path.to_str().unwrap();
+        // no rGFA tags, the contigs are only known from PanSN path names
+        std::fs::write(
+            path,
+            "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nL\t1\t+\t2\t+\t0M\n\
+             P\tGRCh38#0#HLA-A*01:01\t1+,2+\t*\nW\tHG002\t1\tchr6\t0\t5\t>1>2\n",
+        )
+        .unwrap();
+
+        // region strings are read the same way by the lazy and the in-memory graph
+        let lazy = LazyGraph::open(path, 2).unwrap();
+        let graph = gfa_to_graph(path).unwrap();
+        for region in [
+            "HLA-A*01:01",
+            "GRCh38#0#HLA-A*01:01",
+            "HLA-A*01:01:2-3",
+            "chr6",
+            "HG002#1#chr6",
+            "chr6:1-2",
+            "chr7",
+        ] {
+            assert_eq!(
+                Region::parse_with(region, |contig| lazy.has_contig(contig)).ok(),
+                Region::parse_with(region, |contig| graph.has_contig(contig)).ok(),
+                "{}",
+                region
+            );
+        }
+        assert!(lazy.has_contig("HLA-A*01:01"));
+        assert!(!lazy.has_contig("chr7"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,11 +2,25 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 pub mod bfs;
+pub mod bubbles;
+pub mod classify;
 pub mod cli;
 pub mod echart;
 pub mod generate;
 pub mod gfa;
+pub mod gfa2;
 pub mod gml;
+pub mod index;
 pub mod layout;
+pub mod lazy;
+pub mod path;
+pub mod query;
+pub mod region;
 pub mod serve;
+pub mod snarl;
+pub mod source;
+pub mod stats;
+pub mod store;
+pub mod validate;
+pub mod variants;
 pub mod vcf;
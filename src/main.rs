@@ -1,7 +1,14 @@
 use anyhow::Result;
+use fm3_gfa::bubbles::{bubbles, BubbleOptions};
 use fm3_gfa::cli::{parse_cli, Commands};
 use fm3_gfa::generate::generate;
+use fm3_gfa::gfa::SequenceMode;
+use fm3_gfa::index::{index, verify};
+use fm3_gfa::query::query;
 use fm3_gfa::serve::serve;
+use fm3_gfa::stats::stats;
+use fm3_gfa::validate::validate;
+use fm3_gfa::variants::IdIndexPath;
 
 fn main() -> Result<()> {
     // parse cli
@@ -9,11 +16,101 @@ fn main() -> Result<()> {
 
     // match sub-command
     match cli.command {
-        Commands::Generate { gfa, vcf, threads } => generate(&gfa, &vcf, threads)?,
-        Commands::Serve { gfa, vcf, port } => {
-            tokio::runtime::Runtime::new()?.block_on(serve(&gfa, &vcf, port))?
+        Commands::Generate {
+            gfa,
+            vcf,
+            samples,
+            id_index,
+            no_id_index,
+            region,
+            nested,
+            id_scheme,
+            extract,
+            threads,
+            mmap,
+        } => generate(
+            &gfa,
+            vcf.as_deref(),
+            samples.as_deref(),
+            &id_index_path(id_index, no_id_index),
+            region.as_deref(),
+            nested,
+            id_scheme,
+            &extract,
+            threads,
+            sequence_mode(mmap),
+        )?,
+        Commands::Bubbles {
+            gfa,
+            reference,
+            max_nodes,
+            max_alleles,
+            mmap,
+        } => bubbles(
+            &gfa,
+            &BubbleOptions {
+                reference,
+                max_nodes,
+                max_alleles,
+            },
+            sequence_mode(mmap),
+        )?,
+        Commands::Index { gfa, output, mmap } => {
+            index(&gfa, output.as_deref(), sequence_mode(mmap))?
         }
+        Commands::VerifyIndex { index } => verify(&index)?,
+        Commands::Query {
+            gfa,
+            region,
+            extract,
+            mmap,
+        } => query(&gfa, &region, &extract, sequence_mode(mmap))?,
+        Commands::Stats { gfa, json, mmap } => stats(&gfa, json, sequence_mode(mmap))?,
+        Commands::Validate {
+            gfa,
+            vcf,
+            id_scheme,
+            mmap,
+        } => validate(&gfa, &vcf, id_scheme, sequence_mode(mmap))?,
+        Commands::Serve {
+            gfa,
+            vcf,
+            samples,
+            id_index,
+            no_id_index,
+            id_scheme,
+            extract,
+            port,
+            mmap,
+            lazy,
+        } => tokio::runtime::Runtime::new()?.block_on(serve(
+            &gfa,
+            vcf.as_deref(),
+            samples.as_deref(),
+            &id_index_path(id_index, no_id_index),
+            id_scheme,
+            extract,
+            port,
+            sequence_mode(mmap),
+            lazy,
+        ))?,
     }
 
     Ok(())
 }
+
+fn id_index_path(id_index: Option<String>, no_id_index: bool) -> IdIndexPath {
+    match (id_index, no_id_index) {
+        (_, true) => IdIndexPath::Memory,
+        (Some(path), false) => IdIndexPath::At(path),
+        (None, false) => IdIndexPath::Beside,
+    }
+}
+
+fn sequence_mode(mmap: bool) -> SequenceMode {
+    if mmap {
+        SequenceMode::Mmap
+    } else {
+        SequenceMode::Packed
+    }
+}
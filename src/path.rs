@@ -0,0 +1,325 @@
+use crate::gfa::{GraphView, Orientation};
+use crate::vcf::BubbleVariant;
+use anyhow::Result;
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// a haplotype path from a P line or a W line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathData {
+    pub name: String,
+    pub sample: String,
+    pub haplotype: Option<String>,
+    pub contig: String,
+    // 0-based position of the first step on the contig, the SeqStart of a W line
+    pub start: usize,
+    // steps are packed as node index << 1 | reverse
+    steps: Vec<u32>,
+}
+
+impl PathData {
+    pub fn new(name: String, sample: String, haplotype: Option<String>, contig: String) -> Self {
+        Self {
+            name,
+            sample,
+            haplotype,
+            contig,
+            start: 0,
+            steps: Vec::new(),
+        }
+    }
+
+    // split a PanSN path name (sample#haplotype#contig)
+    pub fn from_pansn(name: &str) -> Self {
+        let fields: Vec<&str> = name.split('#').collect();
+        match fields.as_slice() {
+            [sample, hap, contig, ..] => Self::new(
+                name.to_string(),
+                sample.to_string(),
+                Some(hap.to_string()),
+                contig.to_string(),
+            ),
+            [sample, contig] => Self::new(
+                name.to_string(),
+                sample.to_string(),
+                None,
+                contig.to_string(),
+            ),
+            _ => Self::new(name.to_string(), name.to_string(), None, name.to_string()),
+        }
+    }
+
+    pub fn push_step(&mut self, node_idx: NodeIndex, orient: Orientation) {
+        let handle = (node_idx.index() as u32) << 1 | orient.is_reverse() as u32;
+        self.steps.push(handle);
+    }
+
+    // get a step by its rank on the path
+    pub fn step(&self, rank: usize) -> Option<(NodeIndex, Orientation)> {
+        self.steps.get(rank).map(|&handle| Self::unpack(handle))
+    }
+
+    pub fn steps(&self) -> impl Iterator<Item = (NodeIndex, Orientation)> + '_ {
+        self.steps.iter().map(|&handle| Self::unpack(handle))
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    // sample#haplotype, or just the sample if the haplotype is unknown
+    pub fn haplotype_name(&self) -> String {
+        match &self.haplotype {
+            Some(hap) => format!("{}#{}", self.sample, hap),
+            None => self.sample.clone(),
+        }
+    }
+
+    fn unpack(handle: u32) -> (NodeIndex, Orientation) {
+        let orient = if handle & 1 == 1 {
+            Orientation::Backward
+        } else {
+            Orientation::Forward
+        };
+        (NodeIndex::new((handle >> 1) as usize), orient)
+    }
+}
+
+// node -> (path id, step rank) occurrences, stored as CSR arrays
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathIndex {
+    offsets: Vec<usize>,
+    occurrences: Vec<(u32, u32)>,
+}
+
+impl PathIndex {
+    pub fn build(node_count: usize, paths: &[PathData]) -> Self {
+        // count occurrences of every node first
+        let mut offsets = vec![0; node_count + 1];
+        for path in paths {
+            for (node_idx, _) in path.steps() {
+                offsets[node_idx.index() + 1] += 1;
+            }
+        }
+        for i in 0..node_count {
+            offsets[i + 1] += offsets[i];
+        }
+
+        // then fill the buckets
+        let mut fill = offsets.clone();
+        let mut occurrences = vec![(0, 0); offsets[node_count]];
+        for (path_id, path) in paths.iter().enumerate() {
+            for (rank, (node_idx, _)) in path.steps().enumerate() {
+                occurrences[fill[node_idx.index()]] = (path_id as u32, rank as u32);
+                fill[node_idx.index()] += 1;
+            }
+        }
+
+        Self {
+            offsets,
+            occurrences,
+        }
+    }
+
+    // all (path id, step rank) visiting a node
+    pub fn occurrences(&self, node_idx: NodeIndex) -> &[(u32, u32)] {
+        match (
+            self.offsets.get(node_idx.index()),
+            self.offsets.get(node_idx.index() + 1),
+        ) {
+            (Some(&start), Some(&end)) => &self.occurrences[start..end],
+            _ => &[],
+        }
+    }
+}
+
+// a walk step by segment name, before it is resolved to a node index
+pub type WalkStep = (Vec<u8>, Orientation);
+
+// parse a W line: W sample hap_index seq_id seq_start seq_end walk
+pub fn parse_walk_line(line: &[u8]) -> Result<(PathData, Vec<WalkStep>)> {
+    let line = String::from_utf8_lossy(line);
+    let fields: Vec<&str> = line.trim_end().split('\t').collect();
+    if fields.len() < 7 || fields[0] != "W" {
+        return Err(anyhow::anyhow!("Invalid W line: {}", line));
+    }
+    let (sample, hap, contig) = (fields[1], fields[2], fields[3]);
+    let start = match fields[4] {
+        "*" => 0,
+        start => start
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid W line start: {}", start))?,
+    };
+    let mut path = PathData::new(
+        format!("{}#{}#{}", sample, hap, contig),
+        sample.to_string(),
+        Some(hap.to_string()),
+        contig.to_string(),
+    );
+    path.start = start;
+    Ok((path, parse_walk(fields[6])?))
+}
+
+// parse a P line: P name steps overlaps
+pub fn parse_path_line(line: &[u8]) -> Result<(PathData, Vec<WalkStep>)> {
+    let fields: Vec<&[u8]> = line.split(|&b| b == b'\t').collect();
+    if fields.len() < 3 {
+        return Err(anyhow::anyhow!(
+            "Invalid P line: {}",
+            String::from_utf8_lossy(line)
+        ));
+    }
+    let path = PathData::from_pansn(&String::from_utf8_lossy(fields[1]));
+    Ok((path, parse_path_steps(fields[2])?))
+}
+
+// parse a walk string like >s1<s2>s3
+pub fn parse_walk(walk: &str) -> Result<Vec<WalkStep>> {
+    let mut steps = Vec::new();
+    let mut rest = walk;
+    while !rest.is_empty() {
+        let orient = match rest.as_bytes()[0] {
+            b'>' => Orientation::Forward,
+            b'<' => Orientation::Backward,
+            _ => return Err(anyhow::anyhow!("Invalid walk step: {}", rest)),
+        };
+        rest = &rest[1..];
+        let end = rest.find(['>', '<']).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(anyhow::anyhow!("Empty segment name in walk: {}", walk));
+        }
+        steps.push((rest.as_bytes()[..end].to_vec(), orient));
+        rest = &rest[end..];
+    }
+    Ok(steps)
+}
+
+// parse the steps of a P line like s1+,s2-,s3+
+pub fn parse_path_steps(steps: &[u8]) -> Result<Vec<WalkStep>> {
+    steps
+        .split(|&b| b == b',')
+        .map(|step| match step.split_last() {
+            Some((b'+', seg)) if !seg.is_empty() => Ok((seg.to_vec(), Orientation::Forward)),
+            Some((b'-', seg)) if !seg.is_empty() => Ok((seg.to_vec(), Orientation::Backward)),
+            _ => Err(anyhow::anyhow!(
+                "Invalid path step: {}",
+                String::from_utf8_lossy(step)
+            )),
+        })
+        .collect()
+}
+
+// one allele traversal of a bubble and the haplotypes supporting it
+#[derive(Debug, Clone, Serialize)]
+pub struct AlleleSupport {
+    pub allele: usize,
+    // node status of the allele, REF, ALT1, ALT2 ...
+    pub name: String,
+    // None if the GFA has no paths
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub haplotypes: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<String>,
+}
+
+// list every allele traversal, with the haplotype paths walking through it
+pub fn allele_support<G: GraphView + ?Sized>(bubble: &BubbleVariant, g: &G) -> Vec<AlleleSupport> {
+    bubble
+        .allele_traversal
+        .iter()
+        .enumerate()
+        .map(|(allele, traversal)| {
+            let name = BubbleVariant::allele_name(allele);
+            if g.paths().is_empty() {
+                return AlleleSupport {
+                    allele,
+                    name,
+                    haplotypes: None,
+                    samples: Vec::new(),
+                };
+            }
+            let haplotypes = haplotypes_along(&traversal.steps, g);
+            AlleleSupport {
+                allele,
+                name,
+                haplotypes: Some(haplotypes.len()),
+                samples: haplotypes.into_iter().collect(),
+            }
+        })
+        .collect()
+}
+
+// haplotypes containing the steps consecutively, in either direction, a path walking the
+// traversal backwards has every step on the other strand
+fn haplotypes_along<G: GraphView + ?Sized>(steps: &[WalkStep], g: &G) -> BTreeSet<String> {
+    let mut haplotypes = BTreeSet::new();
+    let handles: Option<Vec<(NodeIndex, Orientation)>> = steps
+        .iter()
+        .map(|(id, orient)| Some((g.get_node_idx(id)?, *orient)))
+        .collect();
+    let Some(handles) = handles else {
+        return haplotypes;
+    };
+    let Some(&(first, _)) = handles.first() else {
+        return haplotypes;
+    };
+
+    for &(path_id, rank) in g.path_occurrences(first) {
+        let path = &g.paths()[path_id as usize];
+        let rank = rank as usize;
+        let forward = handles
+            .iter()
+            .enumerate()
+            .all(|(i, &(idx, orient))| path.step(rank + i) == Some((idx, orient)));
+        let backward = rank + 1 >= handles.len()
+            && handles
+                .iter()
+                .enumerate()
+                .all(|(i, &(idx, orient))| path.step(rank - i) == Some((idx, orient.flip())));
+        if forward || backward {
+            haplotypes.insert(path.haplotype_name());
+        }
+    }
+    haplotypes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_walk_line() {
+        let line = b"W\tHG002\t1\tchr1\t0\t100\t>s1<s2>chr1_5000";
+        let (path, steps) = parse_walk_line(line).unwrap();
+
+        assert_eq!(path.sample, "HG002");
+        assert_eq!(path.haplotype.as_deref(), Some("1"));
+        assert_eq!(path.contig, "chr1");
+        assert_eq!(steps[0], (b"s1".to_vec(), Orientation::Forward));
+        assert_eq!(steps[1], (b"s2".to_vec(), Orientation::Backward));
+        assert_eq!(steps[2], (b"chr1_5000".to_vec(), Orientation::Forward));
+    }
+
+    #[test]
+    fn test_parse_path_steps() {
+        let steps = parse_path_steps(b"s1+,s2-").unwrap();
+        assert_eq!(steps[0], (b"s1".to_vec(), Orientation::Forward));
+        assert_eq!(steps[1], (b"s2".to_vec(), Orientation::Backward));
+        assert!(parse_path_steps(b"s1+,s2").is_err());
+    }
+
+    #[test]
+    fn test_pansn_name() {
+        let path = PathData::from_pansn("HG002#2#chr6");
+        assert_eq!(path.haplotype_name(), "HG002#2");
+        assert_eq!(path.contig, "chr6");
+
+        let path = PathData::from_pansn("GRCh38.chr6");
+        assert_eq!(path.haplotype_name(), "GRCh38.chr6");
+    }
+}
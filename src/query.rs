@@ -0,0 +1,31 @@
+// src/query.rs
+use anyhow::Result;
+
+use crate::{
+    bfs::ExtractOptions,
+    echart::EchartGraph,
+    gfa::{GraphView, SequenceMode},
+    region::Region,
+    source::load_graph,
+};
+
+pub fn query(
+    gfa_path: &str,
+    region: &str,
+    extract: &ExtractOptions,
+    mode: SequenceMode,
+) -> Result<()> {
+    // load gfa file
+    let whole_gfa = load_graph(gfa_path, mode)?;
+    let region = Region::parse_with(region, |contig| whole_gfa.has_contig(contig))?;
+
+    // nodes overlapping the region
+    let nodes = whole_gfa.nodes_in_region(&region);
+    eprintln!("Found {} nodes in {}", nodes.len(), region);
+
+    // extract subgraph, layout by FM3 and annotate
+    let echart_graph = EchartGraph::from_region(&region, extract, &whole_gfa)?;
+    println!("{}\t{}", region, echart_graph.oneline_stdout()?);
+
+    Ok(())
+}
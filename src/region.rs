@@ -0,0 +1,250 @@
+use anyhow::Result;
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+// genomic region, stored 0-based half-open, written 1-based inclusive like samtools
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub contig: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Region {
+    pub fn new(contig: String, start: usize, end: usize) -> Self {
+        Self { contig, start, end }
+    }
+
+    // a whole contig, written without a range
+    pub fn whole_contig(contig: String) -> Self {
+        Self::new(contig, 0, usize::MAX)
+    }
+
+    pub fn is_whole_contig(&self) -> bool {
+        self.end == usize::MAX
+    }
+
+    // parse a region, a string that names a known contig is that whole contig even when it
+    // holds a ':' (e.g. HLA-A*01:01:01:01)
+    pub fn parse_with<F: Fn(&str) -> bool>(s: &str, is_contig: F) -> Result<Self> {
+        let s = s.trim();
+        if is_contig(s) {
+            return Ok(Self::whole_contig(s.to_string()));
+        }
+        s.parse()
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // overlap with a 0-based half-open interval on the same contig
+    pub fn overlaps(&self, contig: &str, start: usize, end: usize) -> bool {
+        self.contig == contig && start < self.end && self.start < end
+    }
+}
+
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    // chr6, chr6:31000000 or chr6:31,000,000-31,010,000
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let Some((contig, range)) = s.rsplit_once(':') else {
+            return Ok(Self::whole_contig(s.to_string()));
+        };
+        if contig.is_empty() {
+            return Err(anyhow::anyhow!("Region without contig: {}", s));
+        }
+        let parse_pos = |pos: &str| -> Result<usize> {
+            pos.replace(',', "")
+                .parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("Invalid position in region: {}", s))
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_pos(start)?, parse_pos(end)?),
+            None => {
+                let pos = parse_pos(range)?;
+                (pos, pos)
+            }
+        };
+        if start == 0 || end < start {
+            return Err(anyhow::anyhow!("Invalid range in region: {}", s));
+        }
+        Ok(Self::new(contig.to_string(), start - 1, end))
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_whole_contig() {
+            write!(f, "{}", self.contig)
+        } else {
+            write!(f, "{}:{}-{}", self.contig, self.start + 1, self.end)
+        }
+    }
+}
+
+// rGFA stable coordinate of a segment
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StableCoord {
+    // SN, name of the stable sequence
+    pub name: String,
+    // SO, 0-based offset on the stable sequence
+    pub offset: usize,
+    // SR, rank, 0 for the reference backbone
+    pub rank: usize,
+}
+
+// no stable coordinate for a node
+const NO_CONTIG: u32 = u32::MAX;
+
+// stable coordinates of all nodes and an interval index from them back to nodes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoordIndex {
+    // interned stable sequence names
+    contigs: Vec<String>,
+    contig_ids: HashMap<String, u32>,
+    // node -> (contig id, rank, offset)
+    coords: Vec<(u32, u32, u64)>,
+    // contig id -> (start, end, node) sorted by start
+    intervals: Vec<Vec<(usize, usize, NodeIndex)>>,
+    // longest interval per contig, bounds the binary search
+    max_len: Vec<usize>,
+}
+
+impl CoordIndex {
+    pub fn insert(&mut self, node_idx: NodeIndex, stable: &StableCoord, len: usize) {
+        let contig = match self.contig_ids.get(&stable.name) {
+            Some(&contig) => contig,
+            None => {
+                let contig = self.contigs.len() as u32;
+                self.contigs.push(stable.name.clone());
+                self.contig_ids.insert(stable.name.clone(), contig);
+                self.intervals.push(Vec::new());
+                self.max_len.push(0);
+                contig
+            }
+        };
+        if self.coords.len() <= node_idx.index() {
+            self.coords.resize(node_idx.index() + 1, (NO_CONTIG, 0, 0));
+        }
+        self.coords[node_idx.index()] = (contig, stable.rank as u32, stable.offset as u64);
+
+        let (start, end) = (stable.offset, stable.offset + len);
+        self.intervals[contig as usize].push((start, end, node_idx));
+        let max_len = &mut self.max_len[contig as usize];
+        *max_len = (*max_len).max(len);
+    }
+
+    // sort intervals after all insertions
+    pub fn finish(&mut self) {
+        for intervals in self.intervals.iter_mut() {
+            intervals.sort_unstable_by_key(|&(start, end, _)| (start, end));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contigs.is_empty()
+    }
+
+    pub fn has_contig(&self, contig: &str) -> bool {
+        self.contig_ids.contains_key(contig)
+    }
+
+    // stable coordinate of a node
+    pub fn get(&self, node_idx: NodeIndex) -> Option<StableCoord> {
+        let &(contig, rank, offset) = self.coords.get(node_idx.index())?;
+        if contig == NO_CONTIG {
+            return None;
+        }
+        Some(StableCoord {
+            name: self.contigs[contig as usize].clone(),
+            offset: offset as usize,
+            rank: rank as usize,
+        })
+    }
+
+    // nodes overlapping a region, in coordinate order
+    pub fn query(&self, region: &Region) -> Vec<NodeIndex> {
+        let Some(&contig) = self.contig_ids.get(&region.contig) else {
+            return Vec::new();
+        };
+        let intervals = &self.intervals[contig as usize];
+        let lower = region.start.saturating_sub(self.max_len[contig as usize]);
+        let first = intervals.partition_point(|&(start, _, _)| start < lower);
+        intervals[first..]
+            .iter()
+            .take_while(|&&(start, _, _)| start < region.end)
+            .filter(|&&(start, end, _)| region.overlaps(&region.contig, start, end))
+            .map(|&(_, _, node_idx)| node_idx)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_region() {
+        let region = Region::from_str("chr6:31,000,000-31,010,000").unwrap();
+        assert_eq!(region.contig, "chr6");
+        assert_eq!(region.start, 30_999_999);
+        assert_eq!(region.end, 31_010_000);
+        assert_eq!(region.to_string(), "chr6:31000000-31010000");
+
+        let region = Region::from_str("HG002#1#chr6:100").unwrap();
+        assert_eq!(region.contig, "HG002#1#chr6");
+        assert_eq!(region.len(), 1);
+
+        assert!(Region::from_str("chr6:0-10").is_err());
+        assert!(Region::from_str("chr6:20-10").is_err());
+
+        // a known contig with ':' in its name is not split
+        let hla = "HLA-A*01:01:01:01";
+        let is_hla = |contig: &str| contig == hla;
+        let region = Region::parse_with(hla, is_hla).unwrap();
+        assert_eq!(
+            (region.contig.as_str(), region.is_whole_contig()),
+            (hla, true)
+        );
+        let region = Region::parse_with("HLA-A*01:01:01:01:10-20", is_hla).unwrap();
+        assert_eq!(
+            (region.contig.as_str(), region.start, region.end),
+            (hla, 9, 20)
+        );
+        assert_eq!(Region::from_str(hla).unwrap().contig, "HLA-A*01:01:01");
+    }
+
+    #[test]
+    fn test_coord_index_query() {
+        let stable = |offset| StableCoord {
+            name: "chr1".to_string(),
+            offset,
+            rank: 0,
+        };
+        let mut index = CoordIndex::default();
+        index.insert(NodeIndex::new(0), &stable(0), 100);
+        index.insert(NodeIndex::new(1), &stable(100), 1);
+        index.insert(NodeIndex::new(2), &stable(101), 199);
+        index.finish();
+
+        let region = Region::from_str("chr1:101-150").unwrap();
+        assert_eq!(
+            index.query(&region),
+            vec![NodeIndex::new(1), NodeIndex::new(2)]
+        );
+        let region = Region::from_str("chr2:1-10").unwrap();
+        assert!(index.query(&region).is_empty());
+        assert_eq!(index.get(NodeIndex::new(2)), Some(stable(101)));
+        assert_eq!(index.get(NodeIndex::new(3)), None);
+    }
+}
@@ -1,79 +1,379 @@
 use crate::{
-    bfs::extract_subgraph_by_bfs,
+    bfs::{CombineMode, ExtractOptions, WalkMode},
+    bubbles::{discover_bubbles, BubbleOptions},
+    classify::{classify, VariantClass},
     echart::EchartGraph,
-    gfa::{gfa_to_graph, GFAGraph},
-    gml::{GMLGraph, GMLObject},
-    layout::Layout,
-    vcf::{parse_vcf_file, BubbleVariant},
+    gfa::{GraphView, SequenceMode},
+    lazy::LazyGraph,
+    region::Region,
+    snarl::{nested_bubbles, NestedContext},
+    source::load_graph,
+    variants::{IdIndexPath, VariantSource},
+    vcf::{read_sample_list, IdScheme},
 };
 use actix_files as fs;
 use actix_web::{
-    error::{ErrorInternalServerError, ErrorNotFound},
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound, ErrorServiceUnavailable},
     get, web, App, HttpServer, Responder, Result as ActixResult,
 };
 use anyhow::Result;
-use std::collections::HashMap;
-use std::{str::FromStr, sync::Arc};
+use serde::Deserialize;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+};
 
 pub struct AppState {
-    gfa: Arc<GFAGraph>,
-    variants: Arc<HashMap<String, BubbleVariant>>,
+    gfa: Arc<dyn GraphView + Send + Sync>,
+    variants: Arc<VariantSource>,
+    // context of the layouts, unless a request sets it
+    extract: ExtractOptions,
+    // class and size of every variant, computed in the background from the first filtered or
+    // sorted listing on, the error is kept if it fails
+    summaries: OnceLock<Result<Vec<VariantSummary>, String>>,
+    summaries_started: AtomicBool,
+}
+
+// what /api/variants filters and sorts on
+struct VariantSummary {
+    id: String,
+    chrom: String,
+    pos: usize,
+    // None if a traversal node is missing from the graph
+    class: Option<VariantClass>,
+    size: Option<usize>,
+}
+
+impl AppState {
+    // None until the background classification is done, the first call starts it
+    fn summaries(state: &web::Data<Self>) -> Option<&Result<Vec<VariantSummary>, String>> {
+        if let Some(summaries) = state.summaries.get() {
+            return Some(summaries);
+        }
+        if !state.summaries_started.swap(true, Ordering::SeqCst) {
+            let state = state.clone();
+            std::thread::spawn(move || {
+                // a panic is kept as an error too, or every listing would wait for it forever
+                let summaries = match panic::catch_unwind(AssertUnwindSafe(|| state.classify_all()))
+                {
+                    Ok(summaries) => summaries.map_err(|e| e.to_string()),
+                    Err(_) => Err("Classifying the variants failed".to_string()),
+                };
+                let _ = state.summaries.set(summaries);
+            });
+        }
+        None
+    }
+
+    fn classify_all(&self) -> Result<Vec<VariantSummary>> {
+        let tree = self.variants.snarl_tree()?;
+        let mut summaries = Vec::new();
+        self.variants.for_each(|variant| {
+            let classification = classify(variant, Some(tree), self.gfa.as_ref()).ok();
+            summaries.push(VariantSummary {
+                id: variant.id.clone(),
+                chrom: variant.chrom.clone(),
+                pos: variant.pos,
+                class: classification.map(|c| c.class),
+                size: classification.map(|c| c.size),
+            });
+            Ok(())
+        })?;
+        Ok(summaries)
+    }
 }
 
+// run file IO or a layout on the blocking thread pool, errors are answered with a 500
+async fn blocking<T, F>(f: F) -> ActixResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    web::block(f)
+        .await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?
+        .map_err(|e| ErrorInternalServerError(e.to_string()))
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SortKey {
+    Id,
+    Pos,
+    Class,
+    Size,
+}
+
+#[derive(Deserialize)]
+struct VariantsQuery {
+    // comma separated classes, e.g. INS,DEL
+    class: Option<String>,
+    min_size: Option<usize>,
+    max_size: Option<usize>,
+    sort: Option<SortKey>,
+    // sort in descending order
+    #[serde(default)]
+    desc: bool,
+}
+
+// variant IDs, all of them or filtered by class and size and sorted
 #[get("/api/variants")]
-async fn get_variants(data: web::Data<AppState>) -> impl Responder {
-    let variant_ids: Vec<_> = data.variants.keys().cloned().collect();
-    web::Json(variant_ids)
+async fn get_variants(
+    query: web::Query<VariantsQuery>,
+    data: web::Data<AppState>,
+) -> ActixResult<impl Responder> {
+    let query = query.into_inner();
+    if query.class.is_none()
+        && query.min_size.is_none()
+        && query.max_size.is_none()
+        && query.sort.is_none()
+    {
+        let variants = data.variants.clone();
+        let ids = blocking(move || variants.ids()).await?;
+        return Ok(web::Json(ids));
+    }
+
+    let classes = query
+        .class
+        .as_deref()
+        .map(|classes| {
+            classes
+                .split(',')
+                .map(VariantClass::from_str)
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()
+        .map_err(|e| ErrorBadRequest(e.to_string()))?;
+    let summaries = match AppState::summaries(&data) {
+        Some(Ok(summaries)) => summaries,
+        Some(Err(e)) => return Err(ErrorInternalServerError(e.clone())),
+        None => {
+            return Err(ErrorServiceUnavailable(
+                "Variants are still being classified, retry shortly",
+            ))
+        }
+    };
+
+    let mut selected: Vec<&VariantSummary> = summaries
+        .iter()
+        .filter(|v| match &classes {
+            Some(classes) => v.class.is_some_and(|class| classes.contains(&class)),
+            None => true,
+        })
+        .filter(|v| match query.min_size {
+            Some(min) => v.size.is_some_and(|size| size >= min),
+            None => true,
+        })
+        .filter(|v| match query.max_size {
+            Some(max) => v.size.is_some_and(|size| size <= max),
+            None => true,
+        })
+        .collect();
+    match query.sort.unwrap_or(SortKey::Id) {
+        SortKey::Id => selected.sort_by(|a, b| a.id.cmp(&b.id)),
+        SortKey::Pos => {
+            selected.sort_by(|a, b| (&a.chrom, a.pos, &a.id).cmp(&(&b.chrom, b.pos, &b.id)))
+        }
+        SortKey::Class => selected.sort_by(|a, b| (a.class, &a.id).cmp(&(b.class, &b.id))),
+        SortKey::Size => selected.sort_by(|a, b| (a.size, &a.id).cmp(&(b.size, &b.id))),
+    }
+    if query.desc {
+        selected.reverse();
+    }
+    Ok(web::Json(
+        selected.into_iter().map(|v| v.id.clone()).collect(),
+    ))
+}
+
+// sites of all bubbles overlapping a window, sorted by position
+#[get("/api/region/{region}")]
+async fn get_region(
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> ActixResult<impl Responder> {
+    let region = Region::parse_with(&path.into_inner(), |contig| data.gfa.has_contig(contig))
+        .map_err(|e| ErrorBadRequest(e.to_string()))?;
+    let variants = data.variants.clone();
+    let variants = blocking(move || variants.fetch(&region)).await?;
+    Ok(web::Json(variants))
+}
+
+// level, parent, ancestors and children of a bubble
+#[get("/api/snarl/{variant_id}")]
+async fn get_snarl(
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> ActixResult<impl Responder> {
+    let variant_id = path.into_inner();
+    let variants = data.variants.clone();
+    let node = blocking(move || Ok(variants.snarl_tree()?.node(&variant_id))).await?;
+    Ok(web::Json(node))
+}
+
+#[derive(Deserialize)]
+struct LayoutQuery {
+    // draw the children or the parent of the bubble too
+    nested: Option<NestedContext>,
+    // size of the context, the server options are used for the ones left out
+    flank: Option<usize>,
+    hops: Option<usize>,
+    max_nodes: Option<usize>,
+    max_edges: Option<usize>,
+    max_bp: Option<usize>,
+    combine: Option<CombineMode>,
+    walk: Option<WalkMode>,
+    reference: Option<String>,
+}
+
+impl LayoutQuery {
+    // the options of the request, the server defaults for the ones left out
+    fn options(&self, defaults: &ExtractOptions) -> ExtractOptions {
+        ExtractOptions {
+            flank: self.flank.or(defaults.flank),
+            hops: self.hops.or(defaults.hops),
+            max_nodes: self.max_nodes.or(defaults.max_nodes),
+            max_edges: self.max_edges.or(defaults.max_edges),
+            max_bp: self.max_bp.or(defaults.max_bp),
+            combine: self.combine.unwrap_or(defaults.combine),
+            walk: self.walk.unwrap_or(defaults.walk),
+            reference: self
+                .reference
+                .clone()
+                .or_else(|| defaults.reference.clone()),
+        }
+    }
 }
 
 #[get("/api/layout/{variant_id}")]
 async fn get_layout(
     path: web::Path<String>,
+    query: web::Query<LayoutQuery>,
     data: web::Data<AppState>,
 ) -> ActixResult<impl Responder> {
     let variant_id = path.into_inner();
-    let variant = data
-        .variants
-        .get(&variant_id)
-        .ok_or_else(|| ErrorNotFound("Variant not found"))?;
+    let options = query.options(&data.extract);
+    if options.walk == WalkMode::Reference && !data.gfa.paths_loaded() {
+        return Err(ErrorBadRequest(
+            "walk=reference needs the paths, which could not be loaded",
+        ));
+    }
+    if options.walk == WalkMode::Reference && options.reference.is_none() {
+        return Err(ErrorBadRequest(
+            "walk=reference needs a reference sample or path, e.g. reference=GRCh38",
+        ));
+    }
 
-    let sub_graph = extract_subgraph_by_bfs(variant, &data.gfa)
-        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
-
-    let gml_content = sub_graph.to_gml_string();
-    let origin_g =
-        GMLObject::from_str(&gml_content).map_err(|e| ErrorInternalServerError(e.to_string()))?;
-    let origin_g =
-        GMLGraph::from_gml(origin_g).map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    // None if the variant is not found
+    let context = query.nested;
+    let data = data.clone();
+    let echart_graph = blocking(move || {
+        let Some(variant) = data.variants.get(&variant_id)? else {
+            return Ok(None);
+        };
+        let nested = match context {
+            Some(context) => {
+                let tree = data.variants.snarl_tree()?;
+                nested_bubbles(&variant, context, tree, |id| data.variants.get(id))?
+            }
+            None => Vec::new(),
+        };
+        EchartGraph::from_bubble_nested(&variant, nested, &options, data.gfa.as_ref()).map(Some)
+    })
+    .await?
+    .ok_or_else(|| ErrorNotFound("Variant not found"))?;
 
-    let layout = Layout::new().map_err(|e| ErrorInternalServerError(e.to_string()))?;
-    let res = layout
-        .run(&gml_content)
-        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    Ok(web::Json(echart_graph))
+}
 
-    let layout_graph =
-        GMLObject::from_str(&res).map_err(|e| ErrorInternalServerError(e.to_string()))?;
-    let layout_graph =
-        GMLGraph::from_gml(layout_graph).map_err(|e| ErrorInternalServerError(e.to_string()))?;
+#[get("/api/locus/{region}")]
+async fn get_locus(
+    path: web::Path<String>,
+    query: web::Query<LayoutQuery>,
+    data: web::Data<AppState>,
+) -> ActixResult<impl Responder> {
+    let region = Region::parse_with(&path.into_inner(), |contig| data.gfa.has_contig(contig))
+        .map_err(|e| ErrorBadRequest(e.to_string()))?;
+    // a whole contig would lay out an entire chromosome
+    if region.is_whole_contig() {
+        return Err(ErrorBadRequest(format!(
+            "Region {} has no range, give one as {}:start-end",
+            region, region.contig
+        )));
+    }
+    let options = query.options(&data.extract);
 
-    let echart_graph = EchartGraph::from_gml_anno(layout_graph, origin_g)
-        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    // None if no node overlaps the region
+    let gfa = data.gfa.clone();
+    let echart_graph = blocking(move || {
+        if gfa.nodes_in_region(&region).is_empty() {
+            return Ok(None);
+        }
+        EchartGraph::from_region(&region, &options, gfa.as_ref()).map(Some)
+    })
+    .await?
+    .ok_or_else(|| ErrorNotFound("No nodes found in region"))?;
 
     Ok(web::Json(echart_graph))
 }
 
-pub async fn serve(gfa_path: &str, vcf_path: &str, port: u16) -> Result<()> {
+// lazy is the LRU cache size in segments, the GFA is fully loaded if it is None
+#[allow(clippy::too_many_arguments)]
+pub async fn serve(
+    gfa_path: &str,
+    vcf_path: Option<&str>,
+    samples_path: Option<&str>,
+    id_index_path: &IdIndexPath,
+    scheme: IdScheme,
+    extract: ExtractOptions,
+    port: u16,
+    mode: SequenceMode,
+    lazy: Option<usize>,
+) -> Result<()> {
     // Read GFA file and VCF file
-    let gfa = Arc::new(gfa_to_graph(gfa_path)?);
-
-    let variants: HashMap<_, _> = parse_vcf_file(vcf_path)?
-        .into_iter()
-        .map(|v| (v.id.clone(), v))
-        .collect();
+    let (gfa, variants): (Arc<dyn GraphView + Send + Sync>, VariantSource) = match (vcf_path, lazy)
+    {
+        // a bgzipped VCF with a .tbi or .csi index is read on demand
+        (Some(vcf_path), lazy) => {
+            let samples = samples_path.map(read_sample_list).transpose()?;
+            let variants =
+                VariantSource::open(vcf_path, samples.as_deref(), scheme, id_index_path)?;
+            match lazy {
+                Some(cache_size) => {
+                    let gfa = LazyGraph::open(gfa_path, cache_size)?;
+                    // every layout walks the paths, fail now rather than on the first request
+                    if extract.walk == WalkMode::Reference {
+                        gfa.load_paths()?;
+                    }
+                    (Arc::new(gfa), variants)
+                }
+                None => (Arc::new(load_graph(gfa_path, mode)?), variants),
+            }
+        }
+        // without a VCF the bubbles are found in the whole graph
+        (None, Some(_)) => {
+            anyhow::bail!("--lazy needs a VCF, bubble discovery loads the whole graph")
+        }
+        (None, None) => {
+            let whole_gfa = load_graph(gfa_path, mode)?;
+            let bubbles = discover_bubbles(&whole_gfa, &BubbleOptions::default())?;
+            println!("Found {} bubbles in the graph", bubbles.len());
+            (Arc::new(whole_gfa), VariantSource::from_variants(bubbles))
+        }
+    };
     let variants = Arc::new(variants);
 
     // Prepare app state
-    let app_state = web::Data::new(AppState { gfa, variants });
+    let app_state = web::Data::new(AppState {
+        gfa,
+        variants,
+        extract,
+        summaries: OnceLock::new(),
+        summaries_started: AtomicBool::new(false),
+    });
 
     println!("Server running at http://localhost:{}", port);
 
@@ -82,6 +382,9 @@ pub async fn serve(gfa_path: &str, vcf_path: &str, port: u16) -> Result<()> {
             .app_data(app_state.clone())
             .service(get_variants)
             .service(get_layout)
+            .service(get_snarl)
+            .service(get_region)
+            .service(get_locus)
             .service(fs::Files::new("/", "./static").index_file("index.html"))
     })
     .bind(("127.0.0.1", port))?
@@ -0,0 +1,188 @@
+// src/snarl.rs
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::vcf::BubbleVariant;
+
+// nesting of bubbles from the LV and PS fields of vg deconstruct
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnarlTree {
+    parents: HashMap<String, String>,
+    children: HashMap<String, Vec<String>>,
+    levels: HashMap<String, usize>,
+}
+
+// one bubble of the tree, as served by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct SnarlNode {
+    pub id: String,
+    pub level: Option<usize>,
+    pub parent: Option<String>,
+    // parent first, up to the top level bubble
+    pub ancestors: Vec<String>,
+    pub children: Vec<String>,
+}
+
+impl SnarlTree {
+    pub fn insert(&mut self, id: &str, level: Option<usize>, parent: Option<&str>) {
+        if let Some(level) = level {
+            self.levels.insert(id.to_string(), level);
+        }
+        if let Some(parent) = parent {
+            self.parents.insert(id.to_string(), parent.to_string());
+            self.children
+                .entry(parent.to_string())
+                .or_default()
+                .push(id.to_string());
+        }
+    }
+
+    pub fn from_variants<'a>(variants: impl Iterator<Item = &'a BubbleVariant>) -> Self {
+        let mut tree = Self::default();
+        for variant in variants {
+            tree.insert(&variant.id, variant.level, variant.parent.as_deref());
+        }
+        tree
+    }
+
+    pub fn parent(&self, id: &str) -> Option<&str> {
+        self.parents.get(id).map(|parent| parent.as_str())
+    }
+
+    pub fn children(&self, id: &str) -> &[String] {
+        self.children.get(id).map(|c| c.as_slice()).unwrap_or(&[])
+    }
+
+    // parent, grandparent ... stops on a cycle of PS fields
+    pub fn ancestors(&self, id: &str) -> Vec<String> {
+        let mut ancestors = Vec::new();
+        let mut seen = HashSet::from([id]);
+        let mut current = id;
+        while let Some(parent) = self.parent(current) {
+            if !seen.insert(parent) {
+                break;
+            }
+            ancestors.push(parent.to_string());
+            current = parent;
+        }
+        ancestors
+    }
+
+    pub fn node(&self, id: &str) -> SnarlNode {
+        SnarlNode {
+            id: id.to_string(),
+            level: self.levels.get(id).copied(),
+            parent: self.parent(id).map(|parent| parent.to_string()),
+            ancestors: self.ancestors(id),
+            children: self.children(id).to_vec(),
+        }
+    }
+}
+
+// which related bubbles to draw along with a bubble
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NestedContext {
+    // highlight the nodes of the child bubbles
+    Children,
+    // add the boundary nodes of the parent bubble
+    Parent,
+}
+
+// a related bubble and the nodes it brings to a layout
+#[derive(Debug, Clone, Serialize)]
+pub struct NestedBubble {
+    pub id: String,
+    // child or parent
+    pub relation: String,
+    pub level: Option<usize>,
+    pub nodes: Vec<String>,
+}
+
+impl NestedBubble {
+    // all traversal nodes of a child, only the two boundary nodes of a parent
+    pub fn new(bubble: &BubbleVariant, context: NestedContext) -> Self {
+        let mut nodes: Vec<Vec<u8>> = Vec::new();
+        match context {
+            NestedContext::Children => {
+                for allele in 0..bubble.allele_count() {
+                    for node in bubble.get_allele_nodes(allele, true) {
+                        if !nodes.contains(&node) {
+                            nodes.push(node);
+                        }
+                    }
+                }
+            }
+            NestedContext::Parent => {
+                let ref_nodes = bubble.get_ref_nodes(true);
+                nodes.extend(ref_nodes.first().cloned());
+                nodes.extend(ref_nodes.last().filter(|_| ref_nodes.len() > 1).cloned());
+            }
+        }
+        Self {
+            id: bubble.id.clone(),
+            relation: match context {
+                NestedContext::Children => "child".to_string(),
+                NestedContext::Parent => "parent".to_string(),
+            },
+            level: bubble.level,
+            nodes: nodes
+                .iter()
+                .map(|node| String::from_utf8_lossy(node).into_owned())
+                .collect(),
+        }
+    }
+}
+
+// the children or the parent of a bubble, looked up by ID
+pub fn nested_bubbles<F>(
+    bubble: &BubbleVariant,
+    context: NestedContext,
+    tree: &SnarlTree,
+    mut lookup: F,
+) -> Result<Vec<NestedBubble>>
+where
+    F: FnMut(&str) -> Result<Option<BubbleVariant>>,
+{
+    let ids: Vec<String> = match context {
+        NestedContext::Children => tree.children(&bubble.id).to_vec(),
+        NestedContext::Parent => tree
+            .parent(&bubble.id)
+            .map(str::to_string)
+            .into_iter()
+            .collect(),
+    };
+    let mut nested = Vec::new();
+    for id in ids {
+        if let Some(related) = lookup(&id)? {
+            nested.push(NestedBubble::new(&related, context));
+        }
+    }
+    Ok(nested)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snarl_tree() {
+        let mut tree = SnarlTree::default();
+        tree.insert(">1>9", Some(0), None);
+        tree.insert(">2>5", Some(1), Some(">1>9"));
+        tree.insert(">5>8", Some(1), Some(">1>9"));
+        tree.insert(">3>4", Some(2), Some(">2>5"));
+
+        assert_eq!(tree.children(">1>9"), [">2>5", ">5>8"]);
+        assert_eq!(tree.ancestors(">3>4"), vec![">2>5", ">1>9"]);
+        let node = tree.node(">2>5");
+        assert_eq!(node.level, Some(1));
+        assert_eq!(node.parent.as_deref(), Some(">1>9"));
+        assert_eq!(node.children, vec![">3>4"]);
+
+        // a cycle of PS fields does not loop forever
+        tree.insert(">1>9", None, Some(">3>4"));
+        assert_eq!(tree.ancestors(">3>4"), vec![">2>5", ">1>9"]);
+    }
+}
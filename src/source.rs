@@ -0,0 +1,147 @@
+// src/source.rs
+use anyhow::Result;
+use flate2::read::MultiGzDecoder;
+use noodles::bgzf;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::num::NonZeroUsize;
+
+use crate::gfa::{gfa_to_graph_with, gfa_version, GFAGraph, GfaVersion, SequenceMode};
+use crate::index::{load_index, INDEX_MAGIC};
+
+// input graph formats, detected from the first bytes of a file, never from its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Gfa1,
+    Gfa2,
+    // binary snapshot written by `fm3-gfa index`
+    Index,
+}
+
+// compression of a text input, bgzip is gzip cut into independent blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bgzf,
+}
+
+pub fn compression(path: &str) -> Result<Compression> {
+    let mut header = Vec::with_capacity(14);
+    File::open(path)?.take(14).read_to_end(&mut header)?;
+    if !header.starts_with(&[0x1f, 0x8b]) {
+        return Ok(Compression::None);
+    }
+    // bgzip sets FEXTRA and starts the extra field with a BC subfield
+    if header.len() == 14 && header[3] & 0x04 != 0 && &header[12..14] == b"BC" {
+        Ok(Compression::Bgzf)
+    } else {
+        Ok(Compression::Gzip)
+    }
+}
+
+// open a text file, gzip and bgzip are decompressed as they are read,
+// bgzip blocks on all available cores
+pub fn open_text(path: &str) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    Ok(match compression(path)? {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        Compression::Bgzf => {
+            let workers = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+            Box::new(bgzf::MultithreadedReader::with_worker_count(workers, file))
+        }
+    })
+}
+
+pub fn detect_format(path: &str) -> Result<GraphFormat> {
+    let mut magic = Vec::with_capacity(8);
+    open_text(path)?.take(8).read_to_end(&mut magic)?;
+
+    if magic.starts_with(INDEX_MAGIC) {
+        return Ok(GraphFormat::Index);
+    }
+    // GFA is plain text, its lines start with a record type letter or a comment
+    if magic.is_empty() || magic[0].is_ascii_alphabetic() || magic[0] == b'#' {
+        return Ok(match gfa_version(path)? {
+            GfaVersion::Gfa1 => GraphFormat::Gfa1,
+            GfaVersion::Gfa2 => GraphFormat::Gfa2,
+        });
+    }
+    Err(anyhow::anyhow!("Unknown graph format: {}", path))
+}
+
+// load any supported graph into a GFAGraph, mode only applies to GFA1 text
+pub fn load_graph(path: &str, mode: SequenceMode) -> Result<GFAGraph> {
+    match detect_format(path)? {
+        GraphFormat::Index => load_index(path),
+        _ => gfa_to_graph_with(path, mode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-source-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cases: [(&str, &[u8], Option<GraphFormat>); 5] = [
+            ("a.gfa", b"H\tVN:Z:1.0\nS\t1\tA\n", Some(GraphFormat::Gfa1)),
+            (
+                "b.gfa",
+                b"H\tVN:Z:2.0\nS\t1\t1\tA\n",
+                Some(GraphFormat::Gfa2),
+            ),
+            ("d.fm3i", b"FM3GFAIX\x01\x00", Some(GraphFormat::Index)),
+            ("e.bin", b"\x00\x01\x02\x03", None),
+            ("f.txt", b"12\t34\n", None),
+        ];
+        for (name, content, format) in cases {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            assert_eq!(
+                detect_format(path.to_str().unwrap()).ok(),
+                format,
+                "{}",
+                name
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_gfa() {
+        use crate::gfa::GraphView;
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-gz-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let gfa = b"H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nL\t1\t+\t2\t+\t*\n";
+
+        let gz_path = dir.join("t.gfa.gz");
+        let mut writer = flate2::write::GzEncoder::new(
+            File::create(&gz_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        writer.write_all(gfa).unwrap();
+        writer.finish().unwrap();
+
+        let bgz_path = dir.join("t.gfa.bgz");
+        let mut writer = bgzf::Writer::new(File::create(&bgz_path).unwrap());
+        writer.write_all(gfa).unwrap();
+        writer.finish().unwrap();
+
+        for (path, expected) in [(gz_path, Compression::Gzip), (bgz_path, Compression::Bgzf)] {
+            let path = path.to_str().unwrap();
+            assert_eq!(compression(path).unwrap(), expected);
+            assert_eq!(detect_format(path).unwrap(), GraphFormat::Gfa1);
+            let graph = load_graph(path, SequenceMode::Packed).unwrap();
+            assert_eq!((graph.node_count(), graph.edge_count()), (2, 1));
+            let idx = graph.get_node_idx(b"1").unwrap();
+            assert_eq!(graph.get_sequence(idx).unwrap().as_ref(), b"ACGT");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+// src/stats.rs
+use anyhow::Result;
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::EdgeRef;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use crate::gfa::{GFAGraph, GraphView, SequenceMode};
+use crate::source::load_graph;
+
+// summary of a whole graph, printed as text or JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphStats {
+    pub segments: usize,
+    pub links: usize,
+    pub total_length: u64,
+    pub segment_length: LengthStats,
+    // degree -> number of segments
+    pub degree: BTreeMap<usize, usize>,
+    pub components: ComponentStats,
+    pub self_loops: usize,
+    // segment ends without any link
+    pub tips: usize,
+    // only when the graph has P or W lines
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub samples: BTreeMap<String, SamplePaths>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LengthStats {
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub median: u64,
+    pub n50: u64,
+    // power of ten lower bound (0, 1, 10, 100 ...) -> number of segments
+    pub histogram: BTreeMap<u64, usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComponentStats {
+    pub count: usize,
+    pub largest_segments: usize,
+    pub largest_length: u64,
+    // power of ten lower bound of the segment count -> number of components
+    pub histogram: BTreeMap<u64, usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SamplePaths {
+    pub haplotypes: usize,
+    pub paths: usize,
+    pub steps: usize,
+    pub length: u64,
+}
+
+// bucket of a value in a log10 histogram
+fn log_bucket(value: u64) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    10u64.pow(value.ilog10())
+}
+
+fn bucket_label(bucket: u64) -> String {
+    match bucket {
+        0 => "0".to_string(),
+        _ => format!("{}-{}", bucket, bucket * 10 - 1),
+    }
+}
+
+impl LengthStats {
+    fn from_lengths(mut lengths: Vec<u64>) -> Self {
+        if lengths.is_empty() {
+            return Self::default();
+        }
+        lengths.sort_unstable();
+        let total: u64 = lengths.iter().sum();
+        let mut histogram = BTreeMap::new();
+        for &len in lengths.iter() {
+            *histogram.entry(log_bucket(len)).or_insert(0) += 1;
+        }
+
+        // N50 from the longest segment down
+        let mut acc = 0;
+        let mut n50 = 0;
+        for &len in lengths.iter().rev() {
+            acc += len;
+            if acc * 2 >= total {
+                n50 = len;
+                break;
+            }
+        }
+
+        Self {
+            min: lengths[0],
+            max: lengths[lengths.len() - 1],
+            mean: total as f64 / lengths.len() as f64,
+            median: lengths[lengths.len() / 2],
+            n50,
+            histogram,
+        }
+    }
+}
+
+impl GraphStats {
+    pub fn from_graph(g: &GFAGraph) -> Self {
+        let node_count = g.node_count();
+        let lengths: Vec<u64> = g
+            .node_indices()
+            .map(|idx| g.get_seq_len(idx).unwrap_or(0) as u64)
+            .collect();
+        let total_length = lengths.iter().sum();
+
+        let mut degree = BTreeMap::new();
+        for idx in g.node_indices() {
+            *degree.entry(g.inner_graph.edges(idx).count()).or_insert(0) += 1;
+        }
+
+        // a link leaves the end of a forward segment and enters the start of the next
+        let mut linked_sides = vec![false; node_count * 2];
+        let mut self_loops = 0;
+        let mut components = UnionFind::new(node_count);
+        for edge in g.inner_graph.edge_references() {
+            let (from, to) = (edge.source().index(), edge.target().index());
+            let data = edge.weight();
+            linked_sides[from * 2 + !data.from_orient.is_reverse() as usize] = true;
+            linked_sides[to * 2 + data.to_orient.is_reverse() as usize] = true;
+            if from == to {
+                self_loops += 1;
+            }
+            components.union(from, to);
+        }
+        let tips = linked_sides.iter().filter(|&&linked| !linked).count();
+
+        let mut component_sizes: BTreeMap<usize, (usize, u64)> = BTreeMap::new();
+        for (idx, &len) in lengths.iter().enumerate() {
+            let size = component_sizes.entry(components.find(idx)).or_default();
+            size.0 += 1;
+            size.1 += len;
+        }
+        let mut component_stats = ComponentStats {
+            count: component_sizes.len(),
+            ..ComponentStats::default()
+        };
+        for &(segments, length) in component_sizes.values() {
+            if (segments, length)
+                > (
+                    component_stats.largest_segments,
+                    component_stats.largest_length,
+                )
+            {
+                component_stats.largest_segments = segments;
+                component_stats.largest_length = length;
+            }
+            *component_stats
+                .histogram
+                .entry(log_bucket(segments as u64))
+                .or_insert(0) += 1;
+        }
+
+        let mut samples: BTreeMap<String, SamplePaths> = BTreeMap::new();
+        let mut haplotypes: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for path in g.paths() {
+            let sample = samples.entry(path.sample.clone()).or_default();
+            sample.paths += 1;
+            sample.steps += path.len();
+            sample.length += path
+                .steps()
+                .map(|(idx, _)| lengths[idx.index()])
+                .sum::<u64>();
+            let haps = haplotypes.entry(path.sample.clone()).or_default();
+            if !haps.contains(&path.haplotype_name()) {
+                haps.push(path.haplotype_name());
+            }
+        }
+        for (sample, haps) in haplotypes {
+            if let Some(paths) = samples.get_mut(&sample) {
+                paths.haplotypes = haps.len();
+            }
+        }
+
+        Self {
+            segments: node_count,
+            links: g.edge_count(),
+            total_length,
+            segment_length: LengthStats::from_lengths(lengths),
+            degree,
+            components: component_stats,
+            self_loops,
+            tips,
+            samples,
+        }
+    }
+}
+
+impl Display for GraphStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lens = &self.segment_length;
+        writeln!(f, "Segments\t{}", self.segments)?;
+        writeln!(f, "Links\t{}", self.links)?;
+        writeln!(f, "Total length (bp)\t{}", self.total_length)?;
+        writeln!(
+            f,
+            "Segment length\tmin {}, max {}, mean {:.1}, median {}, N50 {}",
+            lens.min, lens.max, lens.mean, lens.median, lens.n50
+        )?;
+        writeln!(f, "Self-loops\t{}", self.self_loops)?;
+        writeln!(f, "Tips\t{}", self.tips)?;
+        writeln!(
+            f,
+            "Components\t{}, largest {} segments / {} bp",
+            self.components.count, self.components.largest_segments, self.components.largest_length
+        )?;
+
+        writeln!(f, "\nSegment length (bp)\tsegments")?;
+        for (bucket, count) in lens.histogram.iter() {
+            writeln!(f, "{}\t{}", bucket_label(*bucket), count)?;
+        }
+        writeln!(f, "\nDegree\tsegments")?;
+        for (degree, count) in self.degree.iter() {
+            writeln!(f, "{}\t{}", degree, count)?;
+        }
+        writeln!(f, "\nComponent size (segments)\tcomponents")?;
+        for (bucket, count) in self.components.histogram.iter() {
+            writeln!(f, "{}\t{}", bucket_label(*bucket), count)?;
+        }
+        if !self.samples.is_empty() {
+            writeln!(f, "\nSample\thaplotypes\tpaths\tsteps\tlength (bp)")?;
+            for (sample, paths) in self.samples.iter() {
+                writeln!(
+                    f,
+                    "{}\t{}\t{}\t{}\t{}",
+                    sample, paths.haplotypes, paths.paths, paths.steps, paths.length
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn stats(gfa_path: &str, json: bool, mode: SequenceMode) -> Result<()> {
+    let whole_gfa = load_graph(gfa_path, mode)?;
+    let stats = GraphStats::from_graph(&whole_gfa);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print!("{}", stats);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfa::gfa1_from_reader;
+
+    #[test]
+    fn test_graph_stats() {
+        let gfa = "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nS\t3\tGGGGGGGGGG\nS\t4\t*\tLN:i:100\n\
+                   L\t1\t+\t2\t+\t0M\nL\t1\t+\t3\t+\t0M\nL\t2\t+\t3\t+\t0M\nL\t3\t+\t3\t-\t0M\n\
+                   P\tHG002#1#chr1\t1+,2+,3+\t*\nP\tHG002#2#chr1\t1+,3+\t*\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let stats = GraphStats::from_graph(&g);
+
+        assert_eq!(stats.segments, 4);
+        assert_eq!(stats.links, 4);
+        assert_eq!(stats.total_length, 115);
+        assert_eq!(stats.segment_length.n50, 100);
+        assert_eq!(stats.segment_length.median, 10);
+        assert_eq!(stats.self_loops, 1);
+        // start of 1, both ends of 4
+        assert_eq!(stats.tips, 3);
+        assert_eq!(stats.components.count, 2);
+        assert_eq!(stats.components.largest_segments, 3);
+        assert_eq!(stats.degree.get(&0), Some(&1));
+
+        let sample = &stats.samples["HG002"];
+        assert_eq!((sample.haplotypes, sample.paths), (2, 2));
+        assert_eq!(sample.length, 15 + 14);
+    }
+}
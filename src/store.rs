@@ -0,0 +1,360 @@
+use anyhow::Result;
+use memmap2::Mmap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{hash_map::Entry, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// segment names interned in one byte arena, the index of a name is its node index
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SegmentNames {
+    arena: Vec<u8>,
+    // end offset of every name in the arena
+    ends: Vec<u64>,
+    // hash of a name -> index, names are compared against the arena on lookup,
+    // DefaultHasher is not stable across Rust releases so both maps are rebuilt on load
+    #[serde(skip)]
+    lookup: HashMap<u64, u32>,
+    // the rare names whose hash is already taken by another name
+    #[serde(skip)]
+    collisions: HashMap<Vec<u8>, u32>,
+}
+
+impl SegmentNames {
+    fn hash(name: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // intern a new name, it gets the next index
+    pub fn insert(&mut self, name: &[u8]) -> Result<u32> {
+        if self.get(name).is_some() {
+            return Err(anyhow::anyhow!(
+                "Duplicate segment: {}",
+                String::from_utf8_lossy(name)
+            ));
+        }
+        let idx = self.ends.len() as u32;
+        self.arena.extend_from_slice(name);
+        self.ends.push(self.arena.len() as u64);
+        self.index_name(name, idx);
+        Ok(idx)
+    }
+
+    fn index_name(&mut self, name: &[u8], idx: u32) {
+        match self.lookup.entry(Self::hash(name)) {
+            Entry::Occupied(_) => {
+                self.collisions.insert(name.to_vec(), idx);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(idx);
+            }
+        }
+    }
+
+    // hash the interned names again, the lookup is not part of the snapshot
+    pub fn rebuild_lookup(&mut self) {
+        self.lookup = HashMap::with_capacity(self.len());
+        self.collisions.clear();
+        let arena = std::mem::take(&mut self.arena);
+        let mut start = 0;
+        for idx in 0..self.len() {
+            let end = self.ends[idx] as usize;
+            self.index_name(&arena[start..end], idx as u32);
+            start = end;
+        }
+        self.arena = arena;
+    }
+
+    pub fn get(&self, name: &[u8]) -> Option<u32> {
+        if let Some(&idx) = self.collisions.get(name) {
+            return Some(idx);
+        }
+        let &idx = self.lookup.get(&Self::hash(name))?;
+        (self.name(idx as usize) == Some(name)).then_some(idx)
+    }
+
+    pub fn name(&self, idx: usize) -> Option<&[u8]> {
+        let end = *self.ends.get(idx)? as usize;
+        let start = if idx == 0 {
+            0
+        } else {
+            self.ends[idx - 1] as usize
+        };
+        Some(&self.arena[start..end])
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+}
+
+// how a sequence is packed in the blob
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+enum Encoding {
+    Raw = 0,
+    TwoBit = 1,
+    FourBit = 2,
+    // "*", only the length (from LN) is known
+    Missing = 3,
+}
+
+const TWO_BIT_ALPHABET: &[u8; 4] = b"ACGT";
+// softmasked bases and the common IUPAC codes, anything else is stored raw
+const FOUR_BIT_ALPHABET: &[u8; 16] = b"ACGTNacgtnRYKMSW";
+
+// sequences are either packed in memory or raw slices of a memory-mapped file
+#[derive(Debug)]
+enum Blob {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+    // loaded from an index, waiting for the source file to be mapped
+    Unmapped,
+}
+
+impl Blob {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Blob::Owned(blob) => blob,
+            Blob::Mapped(map) => map,
+            Blob::Unmapped => &[],
+        }
+    }
+}
+
+// a mapped blob is saved without its bytes, it has to be mapped again after loading
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Blob::Owned(blob) => Some(blob).serialize(serializer),
+            Blob::Mapped(_) | Blob::Unmapped => None::<&Vec<u8>>.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Ok(match Option::<Vec<u8>>::deserialize(deserializer)? {
+            Some(blob) => Blob::Owned(blob),
+            None => Blob::Unmapped,
+        })
+    }
+}
+
+// segment sequences, stored 2-bit or 4-bit packed when possible
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SequenceStore {
+    blob: Blob,
+    // byte offset of every sequence in the blob
+    starts: Vec<u64>,
+    // sequence length in bases
+    lens: Vec<u32>,
+    encodings: Vec<Encoding>,
+}
+
+impl Default for SequenceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SequenceStore {
+    pub fn new() -> Self {
+        Self {
+            blob: Blob::Owned(Vec::new()),
+            starts: Vec::new(),
+            lens: Vec::new(),
+            encodings: Vec::new(),
+        }
+    }
+
+    // store raw slices of a memory-mapped file instead of packed copies
+    pub fn mapped(map: Mmap) -> Self {
+        Self {
+            blob: Blob::Mapped(map),
+            ..Self::new()
+        }
+    }
+
+    // sequences are offsets into a file rather than packed copies
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.blob, Blob::Mapped(_) | Blob::Unmapped)
+    }
+
+    // map the source file again after loading a mapped store from an index
+    pub fn remap(&mut self, map: Mmap) {
+        self.blob = Blob::Mapped(map);
+    }
+
+    // pack and append a sequence, a memory-mapped store only takes slices of its file
+    pub fn push(&mut self, seq: &[u8]) -> Result<()> {
+        if seq.is_empty() || seq == b"*" {
+            self.push_missing(0);
+            return Ok(());
+        }
+        let Blob::Owned(blob) = &mut self.blob else {
+            return Err(anyhow::anyhow!(
+                "Cannot add a sequence to a graph whose sequences are memory-mapped"
+            ));
+        };
+        let encoding = if seq.iter().all(|b| TWO_BIT_ALPHABET.contains(b)) {
+            Encoding::TwoBit
+        } else if seq.iter().all(|b| FOUR_BIT_ALPHABET.contains(b)) {
+            Encoding::FourBit
+        } else {
+            Encoding::Raw
+        };
+
+        self.starts.push(blob.len() as u64);
+        self.lens.push(seq.len() as u32);
+        self.encodings.push(encoding);
+        match encoding {
+            Encoding::TwoBit => blob.extend(seq.chunks(4).map(|chunk| {
+                chunk.iter().enumerate().fold(0u8, |byte, (i, b)| {
+                    byte | (Self::code(TWO_BIT_ALPHABET, *b) << (i * 2))
+                })
+            })),
+            Encoding::FourBit => blob.extend(seq.chunks(2).map(|chunk| {
+                chunk.iter().enumerate().fold(0u8, |byte, (i, b)| {
+                    byte | (Self::code(FOUR_BIT_ALPHABET, *b) << (i * 4))
+                })
+            })),
+            Encoding::Raw | Encoding::Missing => blob.extend_from_slice(seq),
+        }
+        Ok(())
+    }
+
+    // append a sequence given as "*", len is its LN tag or 0
+    pub fn push_missing(&mut self, len: usize) {
+        self.starts.push(0);
+        self.lens.push(len as u32);
+        self.encodings.push(Encoding::Missing);
+    }
+
+    // append a raw sequence already present in the mapped file
+    pub fn push_mapped(&mut self, start: u64, len: usize) {
+        self.starts.push(start);
+        self.lens.push(len as u32);
+        self.encodings.push(Encoding::Raw);
+    }
+
+    fn code(alphabet: &[u8], base: u8) -> u8 {
+        alphabet.iter().position(|&b| b == base).unwrap_or(0) as u8
+    }
+
+    // get a sequence, borrowed if it is stored raw
+    pub fn get(&self, idx: usize) -> Option<Cow<'_, [u8]>> {
+        let start = *self.starts.get(idx)? as usize;
+        let len = self.lens[idx] as usize;
+        let blob = self.blob.as_slice();
+        let seq = match self.encodings[idx] {
+            Encoding::Missing => Cow::Borrowed(&[][..]),
+            Encoding::Raw => Cow::Borrowed(&blob[start..start + len]),
+            Encoding::TwoBit => Cow::Owned(
+                (0..len)
+                    .map(|i| {
+                        TWO_BIT_ALPHABET[((blob[start + i / 4] >> ((i % 4) * 2)) & 0b11) as usize]
+                    })
+                    .collect(),
+            ),
+            Encoding::FourBit => Cow::Owned(
+                (0..len)
+                    .map(|i| {
+                        FOUR_BIT_ALPHABET
+                            [((blob[start + i / 2] >> ((i % 2) * 4)) & 0b1111) as usize]
+                    })
+                    .collect(),
+            ),
+        };
+        Some(seq)
+    }
+
+    pub fn seq_len(&self, idx: usize) -> Option<usize> {
+        self.lens.get(idx).map(|&len| len as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lens.is_empty()
+    }
+}
+
+// raw optional fields of every segment, tab separated, parsed when a node is read
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SegmentTags {
+    arena: Vec<u8>,
+    ends: Vec<u64>,
+}
+
+impl SegmentTags {
+    pub fn push(&mut self, tags: &[&[u8]]) {
+        for (i, tag) in tags.iter().enumerate() {
+            if i > 0 {
+                self.arena.push(b'\t');
+            }
+            self.arena.extend_from_slice(tag);
+        }
+        self.ends.push(self.arena.len() as u64);
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&[u8]> {
+        let end = *self.ends.get(idx)? as usize;
+        let start = if idx == 0 {
+            0
+        } else {
+            self.ends[idx - 1] as usize
+        };
+        Some(&self.arena[start..end])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_sequences() {
+        let mut store = SequenceStore::new();
+        store.push(b"ACGTACGTA").unwrap();
+        store.push(b"acgtNNRY").unwrap();
+        store.push(b"ACGT-B").unwrap();
+        store.push(b"*").unwrap();
+        store.push_missing(100);
+
+        assert_eq!(store.get(0).unwrap().as_ref(), b"ACGTACGTA");
+        assert_eq!(store.get(1).unwrap().as_ref(), b"acgtNNRY");
+        assert_eq!(store.get(2).unwrap().as_ref(), b"ACGT-B");
+        assert_eq!(store.seq_len(3), Some(0));
+        assert_eq!(store.get(4).unwrap().as_ref(), b"");
+        assert_eq!(store.seq_len(4), Some(100));
+        assert!(store.get(5).is_none());
+
+        let mut mapped = SequenceStore {
+            blob: Blob::Unmapped,
+            ..SequenceStore::new()
+        };
+        assert!(mapped.push(b"ACGT").is_err());
+        assert!(mapped.is_empty());
+    }
+
+    #[test]
+    fn test_intern_names() {
+        let mut names = SegmentNames::default();
+        assert_eq!(names.insert(b"s1").unwrap(), 0);
+        assert_eq!(names.insert(b"chr1_5000").unwrap(), 1);
+        assert!(names.insert(b"s1").is_err());
+
+        assert_eq!(names.get(b"chr1_5000"), Some(1));
+        assert_eq!(names.get(b"s2"), None);
+        assert_eq!(names.name(0), Some(&b"s1"[..]));
+    }
+}
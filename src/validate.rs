@@ -0,0 +1,298 @@
+// src/validate.rs
+use anyhow::Result;
+use noodles::vcf::{self, variant::record::Ids};
+use std::fmt::{self, Display};
+
+use crate::gfa::{reverse_complement, GraphView, Orientation, SequenceMode};
+use crate::path::WalkStep;
+use crate::source::load_graph;
+use crate::vcf::{BubbleVariant, IdScheme, RecordIds, SampleSet};
+
+// one inconsistency between a VCF record and the graph
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    // the record could not be read as a bubble, e.g. a malformed AT
+    Parse(String),
+    // a traversal has fewer than the two boundary nodes
+    ShortTraversal {
+        allele: usize,
+        len: usize,
+    },
+    MissingNode {
+        allele: usize,
+        node: String,
+    },
+    MissingEdge {
+        allele: usize,
+        from: String,
+        to: String,
+    },
+    // number of AT entries is not 1 + number of ALT alleles
+    AlleleCount {
+        traversals: usize,
+        alleles: usize,
+    },
+    // REF/ALT string differs from the sequence spelled by the traversal
+    SequenceMismatch {
+        allele: usize,
+        vcf: String,
+        graph: String,
+    },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Parse(e) => write!(f, "parse_error({})", e),
+            Problem::ShortTraversal { allele, len } => {
+                write!(f, "short_traversal(allele={},nodes={})", allele, len)
+            }
+            Problem::MissingNode { allele, node } => {
+                write!(f, "missing_node(allele={},node={})", allele, node)
+            }
+            Problem::MissingEdge { allele, from, to } => {
+                write!(f, "missing_edge(allele={},{}-{})", allele, from, to)
+            }
+            Problem::AlleleCount {
+                traversals,
+                alleles,
+            } => {
+                write!(f, "allele_count(AT={},VCF={})", traversals, alleles)
+            }
+            Problem::SequenceMismatch { allele, vcf, graph } => {
+                write!(
+                    f,
+                    "sequence_mismatch(allele={},vcf={},graph={})",
+                    allele,
+                    shorten(vcf),
+                    shorten(graph)
+                )
+            }
+        }
+    }
+}
+
+// keep long alleles readable in the report
+fn shorten(seq: &str) -> String {
+    if seq.len() <= 20 {
+        seq.to_string()
+    } else {
+        format!("{}...({}bp)", &seq[..20], seq.len())
+    }
+}
+
+// a step as written in AT, e.g. >12 or <12
+fn step_name((node, orient): &WalkStep) -> String {
+    let arrow = if orient.is_reverse() { '<' } else { '>' };
+    format!("{}{}", arrow, String::from_utf8_lossy(node))
+}
+
+// symbolic and missing alleles carry no sequence to compare
+fn is_symbolic(allele: &str) -> bool {
+    allele.starts_with('<') || allele.contains(['[', ']']) || allele == "*" || allele == "."
+}
+
+// check traversals of a bubble against the graph, alleles are REF followed by ALTs
+pub fn validate_bubble<G: GraphView + ?Sized>(
+    bubble: &BubbleVariant,
+    alleles: &[String],
+    g: &G,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if bubble.allele_traversal.len() != alleles.len() {
+        problems.push(Problem::AlleleCount {
+            traversals: bubble.allele_traversal.len(),
+            alleles: alleles.len(),
+        });
+    }
+
+    for (allele, traversal) in bubble.allele_traversal.iter().enumerate() {
+        let steps = &traversal.steps;
+        if steps.len() < 2 {
+            problems.push(Problem::ShortTraversal {
+                allele,
+                len: steps.len(),
+            });
+        }
+
+        let mut handles = Vec::with_capacity(steps.len());
+        for (node, orient) in steps {
+            match g.get_node_idx(node) {
+                Some(idx) => handles.push((idx, *orient)),
+                None => problems.push(Problem::MissingNode {
+                    allele,
+                    node: String::from_utf8_lossy(node).into_owned(),
+                }),
+            }
+        }
+        if handles.len() < steps.len() {
+            continue;
+        }
+
+        // the link has to join the two steps on the strands they are walked
+        for (pair, ids) in handles.windows(2).zip(steps.windows(2)) {
+            let joined = g
+                .edges(pair[0].0)
+                .iter()
+                .any(|(_, from, to, data)| data.walks(*from, *to, pair[0], pair[1]));
+            if !joined {
+                problems.push(Problem::MissingEdge {
+                    allele,
+                    from: step_name(&ids[0]),
+                    to: step_name(&ids[1]),
+                });
+            }
+        }
+
+        let Some(vcf_allele) = alleles.get(allele) else {
+            continue;
+        };
+        if steps.len() < 2 || is_symbolic(vcf_allele) {
+            continue;
+        }
+        // the allele spells the inner steps, reverse steps are read on the reverse strand
+        let spell = |&(idx, orient): &(_, Orientation)| {
+            let sequence = g.get_node_data(idx)?.sequence;
+            Some(match orient {
+                Orientation::Forward => sequence,
+                Orientation::Backward => reverse_complement(&sequence),
+            })
+        };
+        let inner: String = handles[1..handles.len() - 1]
+            .iter()
+            .filter_map(spell)
+            .collect();
+        // deletions are padded with the last base of the first boundary step
+        let padding = spell(&handles[0])
+            .and_then(|sequence| sequence.chars().last())
+            .map(|base| format!("{}{}", base, inner))
+            .unwrap_or_default();
+        if !vcf_allele.eq_ignore_ascii_case(&inner) && !vcf_allele.eq_ignore_ascii_case(&padding) {
+            problems.push(Problem::SequenceMismatch {
+                allele,
+                vcf: vcf_allele.clone(),
+                graph: inner,
+            });
+        }
+    }
+    problems
+}
+
+// stream the VCF and write one TSV line per variant: ID, CHROM, POS, STATUS, PROBLEMS
+pub fn validate(
+    gfa_path: &str,
+    vcf_path: &str,
+    scheme: IdScheme,
+    mode: SequenceMode,
+) -> Result<()> {
+    let whole_gfa = load_graph(gfa_path, mode)?;
+    let mut reader = vcf::io::reader::Builder::default().build_from_path(vcf_path)?;
+    let header = reader.read_header()?;
+    // genotypes are not checked
+    let samples = SampleSet::new(&header, Some(&[]))?;
+    let mut ids = RecordIds::new(scheme);
+
+    let (mut total, mut failed) = (0, 0);
+    println!("#ID\tCHROM\tPOS\tSTATUS\tPROBLEMS");
+    for result in reader.records() {
+        let record = result?;
+        let pos = match record.variant_start().transpose() {
+            Ok(Some(pos)) => pos.get().to_string(),
+            _ => ".".to_string(),
+        };
+
+        let (id, problems) =
+            match BubbleVariant::from_vcf_record_with(&record, &header, &samples, &mut ids) {
+                Ok(bubble) => {
+                    let problems = validate_bubble(&bubble, &bubble.alleles(), &whole_gfa);
+                    (bubble.id, problems)
+                }
+                Err(e) => {
+                    let id = record.ids().iter().next().unwrap_or(".").to_string();
+                    (id, vec![Problem::Parse(e.to_string())])
+                }
+            };
+
+        total += 1;
+        let (status, problems) = if problems.is_empty() {
+            ("OK", ".".to_string())
+        } else {
+            failed += 1;
+            let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+            ("FAIL", problems.join(";"))
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            id,
+            record.reference_sequence_name(),
+            pos,
+            status,
+            problems
+        );
+    }
+    eprintln!("Validated {} variants, {} with problems", total, failed);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfa::gfa1_from_reader;
+    use crate::vcf::Traversal;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_validate_bubble() {
+        let gfa = "H\tVN:Z:1.0\nS\t1\tACGT\nS\t2\tA\nS\t3\tGGG\nS\t4\tT\n\
+                   L\t1\t+\t2\t+\t0M\nL\t2\t+\t4\t+\t0M\nL\t1\t+\t3\t+\t0M\nL\t1\t+\t4\t+\t0M\n";
+        let g = gfa1_from_reader(gfa.as_bytes()).unwrap();
+        let bubble = |ats: &[&str]| {
+            let ats = ats.iter().map(|at| Traversal::from_str(at).unwrap());
+            BubbleVariant::new("v1".to_string(), 5, ats.collect()).unwrap()
+        };
+        let alleles = |alleles: &[&str]| alleles.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        // SNP-like bubble, and a deletion padded with the last base of node 1
+        let ok = bubble(&[">1>2>4", ">1>4"]);
+        assert!(validate_bubble(&ok, &alleles(&["A", "<DEL>"]), &g).is_empty());
+        assert!(validate_bubble(&ok, &alleles(&["TA", "T"]), &g).is_empty());
+
+        // node 3 is walked on its reverse strand, through the links 1+ 3- and 3- 4+
+        let inverted = bubble(&[">1>2>4", ">1<3>4"]);
+        let gfa_inv = gfa.replace("L\t1\t+\t3\t+", "L\t1\t+\t3\t-") + "L\t3\t-\t4\t+\t0M\n";
+        let g_inv = gfa1_from_reader(gfa_inv.as_bytes()).unwrap();
+        assert!(validate_bubble(&inverted, &alleles(&["A", "CCC"]), &g_inv).is_empty());
+        // the same steps on the forward strand have no link
+        assert_eq!(
+            validate_bubble(&inverted, &alleles(&["A", "CCC"]), &g).len(),
+            2
+        );
+
+        let bad = bubble(&[">1>2>4", ">1>3>4", ">1>9>4"]);
+        let problems = validate_bubble(&bad, &alleles(&["A", "C"]), &g);
+        assert_eq!(
+            problems,
+            vec![
+                Problem::AlleleCount {
+                    traversals: 3,
+                    alleles: 2
+                },
+                Problem::MissingEdge {
+                    allele: 1,
+                    from: ">3".to_string(),
+                    to: ">4".to_string()
+                },
+                Problem::SequenceMismatch {
+                    allele: 1,
+                    vcf: "C".to_string(),
+                    graph: "GGG".to_string()
+                },
+                Problem::MissingNode {
+                    allele: 2,
+                    node: "9".to_string()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,648 @@
+// src/variants.rs
+use anyhow::Result;
+use noodles::{
+    bgzf, core,
+    csi::{self, BinningIndex},
+    tabix, vcf,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+
+use crate::region::Region;
+use crate::snarl::SnarlTree;
+use crate::vcf::{parse_vcf_file_with, BubbleVariant, IdScheme, RecordIds, SampleSet};
+
+const ID_INDEX_MAGIC: &[u8; 8] = b"FM3VCFID";
+// bump whenever the layout of VariantIdIndex changes in a release
+const ID_INDEX_VERSION: u32 = 1;
+
+// (ID before the suffix, IDs in file order) of the records of one position sharing that ID
+type RenamedIds = Vec<(String, Vec<String>)>;
+
+// variant ID -> (contig, position), written next to the VCF as <vcf>.fm3v
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VariantIdIndex {
+    // size, modification time and hash of the ends of the VCF, the index is rebuilt when they
+    // change
+    size: u64,
+    mtime: u128,
+    hash: u64,
+    // IDs of records without one depend on the scheme
+    scheme: IdScheme,
+    contigs: Vec<String>,
+    // (id, contig id, 1-based position) sorted by id
+    entries: Vec<(String, u32, u64)>,
+    // IDs given to the records that got a suffix, and to the other records with the same ID
+    // before the suffix at the same position, by contig id and position, then by that ID,
+    // in file order
+    renamed: BTreeMap<(u32, u64), RenamedIds>,
+    // LV/PS nesting of the bubbles
+    tree: SnarlTree,
+}
+
+// where the ID index of an indexed VCF is kept
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum IdIndexPath {
+    // <vcf>.fm3v next to the VCF
+    #[default]
+    Beside,
+    // another file, e.g. when the VCF is in a read-only directory
+    At(String),
+    // built on every start and never written
+    Memory,
+}
+
+// bytes read at each end of the VCF for its hash
+const STAMP_SAMPLE: u64 = 64 * 1024;
+
+// 64-bit FNV-1a, stable across Rust releases; a CRC32 would not see changes to whole bgzf
+// blocks, as each one ends with the CRC32 of its data
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+// size, modification time and a hash of the first and last bytes of a file, so a rewrite
+// that keeps the size and mtime is still seen unless it only touches the middle
+fn file_stamp(path: &Path) -> Result<(u64, u128, u64)> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos();
+    let len = metadata.len();
+
+    let mut file = File::open(path)?;
+    let mut head = vec![0; len.min(STAMP_SAMPLE) as usize];
+    file.read_exact(&mut head)?;
+    let mut hash = fnv1a(0xcbf2_9ce4_8422_2325, &head);
+    if len > STAMP_SAMPLE {
+        file.seek(SeekFrom::Start(STAMP_SAMPLE.max(len - STAMP_SAMPLE)))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        hash = fnv1a(hash, &tail);
+    }
+    Ok((len, mtime, hash))
+}
+
+impl VariantIdIndex {
+    // read CHROM, POS and ID of every record
+    pub fn build(vcf_path: &str, scheme: IdScheme) -> Result<Self> {
+        let (size, mtime, hash) = file_stamp(Path::new(vcf_path))?;
+        let mut reader = vcf::io::reader::Builder::default().build_from_path(vcf_path)?;
+        let header = reader.read_header()?;
+
+        let mut contigs: Vec<String> = Vec::new();
+        let mut entries = Vec::new();
+        let mut tree = SnarlTree::default();
+        let mut ids = RecordIds::new(scheme);
+        // (ID before the suffix, ID) of the records at the last position, the file is sorted
+        let mut site: Vec<(String, String)> = Vec::new();
+        let mut renamed = BTreeMap::new();
+        for result in reader.records() {
+            let record = result?;
+            let chrom = record.reference_sequence_name();
+            if contigs.last().map(|c| c.as_str()) != Some(chrom) {
+                contigs.push(chrom.to_string());
+            }
+            let Some(pos) = record.variant_start().transpose()? else {
+                continue;
+            };
+            let contig = contigs.len() as u32 - 1;
+            let pos = pos.get() as u64;
+            if let Some(&(_, c, p)) = entries.last() {
+                if (c, p) != (contig, pos) {
+                    Self::keep_renamed(&mut renamed, &mut site, c, p);
+                }
+            }
+            let (level, parent) = BubbleVariant::get_nesting(&record, &header)?;
+            let (raw, _) = ids.record_id(&record)?;
+            let id = ids.next(&record)?;
+            tree.insert(&id, level, parent.as_deref());
+            site.push((raw, id.clone()));
+            entries.push((id, contig, pos));
+        }
+        if let Some(&(_, c, p)) = entries.last() {
+            Self::keep_renamed(&mut renamed, &mut site, c, p);
+        }
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(Self {
+            size,
+            mtime,
+            hash,
+            scheme,
+            contigs,
+            entries,
+            renamed,
+            tree,
+        })
+    }
+
+    // keep the IDs of the records of one position that share an ID before the suffix, if
+    // any of them got a suffix
+    fn keep_renamed(
+        renamed: &mut BTreeMap<(u32, u64), RenamedIds>,
+        site: &mut Vec<(String, String)>,
+        contig: u32,
+        pos: u64,
+    ) {
+        let mut groups: RenamedIds = Vec::new();
+        for (raw, id) in site.drain(..) {
+            match groups.iter_mut().find(|(group, _)| *group == raw) {
+                Some((_, ids)) => ids.push(id),
+                None => groups.push((raw, vec![id])),
+            }
+        }
+        groups.retain(|(raw, ids)| ids.iter().any(|id| id != raw));
+        if !groups.is_empty() {
+            renamed.insert((contig, pos), groups);
+        }
+    }
+
+    // the IDs kept by keep_renamed on a contig between two 1-based positions, for
+    // RecordIds::with_given
+    pub fn renamed_between(
+        &self,
+        contig: &str,
+        start: usize,
+        end: usize,
+    ) -> HashMap<(String, String, usize), VecDeque<String>> {
+        let mut given = HashMap::new();
+        // a contig is listed again for every run of its records in an unsorted file
+        for (c, _) in self
+            .contigs
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| *c == contig)
+        {
+            let range = (c as u32, start as u64)..=(c as u32, end as u64);
+            for (&(_, pos), groups) in self.renamed.range(range) {
+                for (raw, ids) in groups {
+                    let key = (raw.clone(), contig.to_string(), pos as usize);
+                    given.insert(key, ids.iter().cloned().collect());
+                }
+            }
+        }
+        given
+    }
+
+    fn write(&self, index_path: &str) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(index_path)?);
+        writer.write_all(ID_INDEX_MAGIC)?;
+        bincode::serialize_into(&mut writer, &ID_INDEX_VERSION)?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // None if the file is not an up to date index of the VCF
+    fn read(index_path: &str, vcf_path: &str, scheme: IdScheme) -> Result<Option<Self>> {
+        let mut reader = BufReader::new(File::open(index_path)?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        let version: u32 = bincode::deserialize_from(&mut reader)?;
+        if &magic != ID_INDEX_MAGIC || version != ID_INDEX_VERSION {
+            return Ok(None);
+        }
+        let index: Self = bincode::deserialize_from(&mut reader)?;
+        let stamp = file_stamp(Path::new(vcf_path))?;
+        Ok(
+            (stamp == (index.size, index.mtime, index.hash) && index.scheme == scheme)
+                .then_some(index),
+        )
+    }
+
+    // load the index file, building it first if it is missing or stale; it is only kept in
+    // memory if it cannot be written
+    pub fn load_or_build(vcf_path: &str, scheme: IdScheme, location: &IdIndexPath) -> Result<Self> {
+        let index_path = match location {
+            IdIndexPath::Beside => format!("{}.fm3v", vcf_path),
+            IdIndexPath::At(path) => path.clone(),
+            IdIndexPath::Memory => {
+                eprintln!("Building variant ID index of {} in memory", vcf_path);
+                return Self::build(vcf_path, scheme);
+            }
+        };
+        if Path::new(&index_path).exists() {
+            if let Ok(Some(index)) = Self::read(&index_path, vcf_path, scheme) {
+                return Ok(index);
+            }
+        }
+        eprintln!("Building variant ID index {}", index_path);
+        let index = Self::build(vcf_path, scheme)?;
+        if let Err(e) = index.write(&index_path) {
+            eprintln!("Cannot write {}: {}", index_path, e);
+        }
+        Ok(index)
+    }
+
+    pub fn get(&self, id: &str) -> Option<(&str, usize)> {
+        let i = self
+            .entries
+            .binary_search_by(|entry| entry.0.as_str().cmp(id))
+            .ok()?;
+        let (_, contig, pos) = &self.entries[i];
+        Some((&self.contigs[*contig as usize], *pos as usize))
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> + '_ {
+        self.entries.iter().map(|entry| entry.0.as_str())
+    }
+
+    pub fn snarl_tree(&self) -> &SnarlTree {
+        &self.tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// bgzipped VCF read by region through its .tbi or .csi index
+pub struct IndexedVcf {
+    path: String,
+    index: Box<dyn BinningIndex + Send + Sync>,
+    header: vcf::Header,
+    samples: SampleSet,
+    scheme: IdScheme,
+    id_index_path: IdIndexPath,
+    // IDs of the whole file, for lookups by ID and for the suffixes of queried records,
+    // loaded on first use
+    ids: OnceLock<VariantIdIndex>,
+}
+
+impl IndexedVcf {
+    pub fn open(
+        vcf_path: &str,
+        samples: Option<&[String]>,
+        scheme: IdScheme,
+        id_index_path: &IdIndexPath,
+    ) -> Result<Self> {
+        let tbi = format!("{}.tbi", vcf_path);
+        let index: Box<dyn BinningIndex + Send + Sync> = if Path::new(&tbi).exists() {
+            Box::new(tabix::fs::read(&tbi)?)
+        } else {
+            Box::new(csi::fs::read(format!("{}.csi", vcf_path))?)
+        };
+        let header = Self::reader(vcf_path)?.read_header()?;
+        let samples = SampleSet::new(&header, samples)?;
+        Ok(Self {
+            path: vcf_path.to_string(),
+            index,
+            header,
+            samples,
+            scheme,
+            id_index_path: id_index_path.clone(),
+            ids: OnceLock::new(),
+        })
+    }
+
+    pub fn id_index(&self) -> Result<&VariantIdIndex> {
+        if let Some(ids) = self.ids.get() {
+            return Ok(ids);
+        }
+        let ids = VariantIdIndex::load_or_build(&self.path, self.scheme, &self.id_index_path)?;
+        Ok(self.ids.get_or_init(|| ids))
+    }
+
+    // every query opens its own reader, so requests do not wait on each other
+    fn reader(vcf_path: &str) -> Result<vcf::io::Reader<bgzf::Reader<File>>> {
+        Ok(vcf::io::Reader::new(bgzf::Reader::new(File::open(
+            vcf_path,
+        )?)))
+    }
+
+    // bubbles overlapping a region, sorted by position
+    pub fn fetch(&self, region: &Region) -> Result<Vec<BubbleVariant>> {
+        // built from its parts, a contig name may hold a ':'
+        let query = if region.is_whole_contig() {
+            core::Region::new(region.contig.as_str(), ..)
+        } else {
+            let start = core::Position::try_from(region.start + 1)?;
+            let end = core::Position::try_from(region.end)?;
+            core::Region::new(region.contig.as_str(), start..=end)
+        };
+        let mut reader = Self::reader(&self.path)?;
+        let records = reader
+            .query(&self.header, &self.index, &query)?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let positions: Vec<usize> = records
+            .iter()
+            .filter_map(|record| record.variant_start().transpose().ok().flatten())
+            .map(|pos| pos.get())
+            .collect();
+        let (Some(&first), Some(&last)) = (positions.iter().min(), positions.iter().max()) else {
+            return Ok(Vec::new());
+        };
+
+        // records get the IDs of a pass over the whole file, as listed by the ID index
+        let given = self
+            .id_index()?
+            .renamed_between(&region.contig, first, last);
+        let mut ids = RecordIds::with_given(self.scheme, given);
+        let mut variants = Vec::new();
+        for record in records {
+            variants.push(BubbleVariant::from_vcf_record_with(
+                &record,
+                &self.header,
+                &self.samples,
+                &mut ids,
+            )?);
+        }
+        variants.sort_by(|a, b| (a.pos, &a.id).cmp(&(b.pos, &b.id)));
+        Ok(variants)
+    }
+
+    // every record of the file, in file order
+    pub fn for_each<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&BubbleVariant) -> Result<()>,
+    {
+        let mut reader = Self::reader(&self.path)?;
+        reader.read_header()?;
+        let mut ids = RecordIds::new(self.scheme);
+        for result in reader.records() {
+            let record = result?;
+            f(&BubbleVariant::from_vcf_record_with(
+                &record,
+                &self.header,
+                &self.samples,
+                &mut ids,
+            )?)?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<BubbleVariant>> {
+        let Some((contig, pos)) = self.id_index()?.get(id) else {
+            return Ok(None);
+        };
+        let region = Region::new(contig.to_string(), pos - 1, pos);
+        Ok(self.fetch(&region)?.into_iter().find(|v| v.id == id))
+    }
+}
+
+// the variants served by the web server
+pub enum VariantSource {
+    // a VCF without index, all records are parsed at startup
+    Memory(HashMap<String, BubbleVariant>, SnarlTree),
+    Indexed(Box<IndexedVcf>),
+}
+
+impl VariantSource {
+    // whether a .tbi or .csi index sits next to the VCF
+    pub fn is_indexed(vcf_path: &str) -> bool {
+        ["tbi", "csi"]
+            .iter()
+            .any(|ext| Path::new(&format!("{}.{}", vcf_path, ext)).exists())
+    }
+
+    // use the .tbi or .csi index next to the VCF when there is one
+    pub fn open(
+        vcf_path: &str,
+        samples: Option<&[String]>,
+        scheme: IdScheme,
+        id_index_path: &IdIndexPath,
+    ) -> Result<Self> {
+        if Self::is_indexed(vcf_path) {
+            return Ok(VariantSource::Indexed(Box::new(IndexedVcf::open(
+                vcf_path,
+                samples,
+                scheme,
+                id_index_path,
+            )?)));
+        }
+        Ok(Self::from_variants(parse_vcf_file_with(
+            vcf_path, samples, scheme,
+        )?))
+    }
+
+    // bubbles already in memory, e.g. found in the graph without a VCF
+    pub fn from_variants(variants: Vec<BubbleVariant>) -> Self {
+        let variants: HashMap<_, _> = variants.into_iter().map(|v| (v.id.clone(), v)).collect();
+        let tree = SnarlTree::from_variants(variants.values());
+        VariantSource::Memory(variants, tree)
+    }
+
+    pub fn ids(&self) -> Result<Vec<String>> {
+        match self {
+            VariantSource::Memory(variants, _) => Ok(variants.keys().cloned().collect()),
+            VariantSource::Indexed(vcf) => {
+                Ok(vcf.id_index()?.ids().map(|id| id.to_string()).collect())
+            }
+        }
+    }
+
+    // bubbles overlapping a region, sorted by position
+    pub fn fetch(&self, region: &Region) -> Result<Vec<BubbleVariant>> {
+        match self {
+            VariantSource::Memory(variants, _) => {
+                let mut overlapping: Vec<_> = variants
+                    .values()
+                    .filter(|v| v.overlaps(region))
+                    .cloned()
+                    .collect();
+                overlapping.sort_by(|a, b| (a.pos, &a.id).cmp(&(b.pos, &b.id)));
+                Ok(overlapping)
+            }
+            VariantSource::Indexed(vcf) => vcf.fetch(region),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<BubbleVariant>> {
+        match self {
+            VariantSource::Memory(variants, _) => Ok(variants.get(id).cloned()),
+            VariantSource::Indexed(vcf) => vcf.get(id),
+        }
+    }
+
+    // visit every bubble, without keeping an indexed VCF in memory
+    pub fn for_each<F>(&self, f: F) -> Result<()>
+    where
+        F: FnMut(&BubbleVariant) -> Result<()>,
+    {
+        match self {
+            VariantSource::Memory(variants, _) => variants.values().try_for_each(f),
+            VariantSource::Indexed(vcf) => vcf.for_each(f),
+        }
+    }
+
+    pub fn snarl_tree(&self) -> Result<&SnarlTree> {
+        match self {
+            VariantSource::Memory(_, tree) => Ok(tree),
+            VariantSource::Indexed(vcf) => Ok(vcf.id_index()?.snarl_tree()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_vcf() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-variants-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let vcf_path = dir.join("t.vcf.gz");
+        let vcf_path = vcf_path.to_str().unwrap();
+
+        let mut writer = bgzf::Writer::new(File::create(vcf_path).unwrap());
+        writer
+            .write_all(
+                b"##fileformat=VCFv4.2\n\
+##INFO=<ID=AT,Number=R,Type=String,Description=\"Allele Traversal\">\n\
+##contig=<ID=chr1>\n##contig=<ID=chr2>\n\
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+chr1\t5\tv1\tA\tC\t.\t.\tAT=>1>2>5,>1>3>5\n\
+chr1\t90\tv2\tA\tC\t.\t.\tAT=>5>6>8,>5>7>8\n\
+chr2\t5\tv3\tA\tC\t.\t.\tAT=>9>10>12,>9>11>12\n",
+            )
+            .unwrap();
+        writer.finish().unwrap();
+        let index = vcf::index(vcf_path).unwrap();
+        tabix::fs::write(format!("{}.tbi", vcf_path), &index).unwrap();
+
+        let source =
+            VariantSource::open(vcf_path, None, IdScheme::default(), &IdIndexPath::Beside).unwrap();
+        assert!(matches!(source, VariantSource::Indexed(_)));
+        let mut ids = source.ids().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec!["v1", "v2", "v3"]);
+        assert_eq!(source.get("v3").unwrap().unwrap().pos, 5);
+        assert!(source.get("v9").unwrap().is_none());
+        assert!(Path::new(&format!("{}.fm3v", vcf_path)).exists());
+
+        let region: Region = "chr1:1-100".parse().unwrap();
+        let variants = source.fetch(&region).unwrap();
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[1].id, "v2");
+        assert_eq!((variants[1].chrom.as_str(), variants[1].end), ("chr1", 90));
+
+        // the ID index can be written elsewhere, or kept in memory only
+        let beside = format!("{}.fm3v", vcf_path);
+        fs::remove_file(&beside).unwrap();
+        let elsewhere = dir.join("ids.fm3v").to_str().unwrap().to_string();
+        let at = IdIndexPath::At(elsewhere.clone());
+        let source = VariantSource::open(vcf_path, None, IdScheme::default(), &at).unwrap();
+        assert_eq!(source.get("v2").unwrap().unwrap().pos, 90);
+        assert!(Path::new(&elsewhere).exists());
+        let memory = IdIndexPath::Memory;
+        let source = VariantSource::open(vcf_path, None, IdScheme::default(), &memory).unwrap();
+        assert_eq!(source.get("v1").unwrap().unwrap().pos, 5);
+        assert!(!Path::new(&beside).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_indexed_suffixed_ids() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-suffixed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let vcf_path = dir.join("t.vcf.gz");
+        let vcf_path = vcf_path.to_str().unwrap();
+
+        // two records without ID at chr1:5, and the ID of the first given explicitly at chr1:20
+        let mut writer = bgzf::Writer::new(File::create(vcf_path).unwrap());
+        writer
+            .write_all(
+                b"##fileformat=VCFv4.2\n\
+##INFO=<ID=AT,Number=R,Type=String,Description=\"Allele Traversal\">\n\
+##contig=<ID=chr1>\n\
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+chr1\t5\t.\tA\tC\t.\t.\tAT=>1>2>5,>1>3>5\n\
+chr1\t5\t.\tA\tC\t.\t.\tAT=>1>2>5,>1>3>5\n\
+chr1\t20\tchr1:5:A:C\tA\tC\t.\t.\tAT=>5>6>8,>5>7>8\n",
+            )
+            .unwrap();
+        writer.finish().unwrap();
+        let index = vcf::index(vcf_path).unwrap();
+        tabix::fs::write(format!("{}.tbi", vcf_path), &index).unwrap();
+
+        // every listed ID is found, whatever part of the file a query sees
+        let source =
+            VariantSource::open(vcf_path, None, IdScheme::default(), &IdIndexPath::Beside).unwrap();
+        let mut ids = source.ids().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec!["chr1:5:A:C", "chr1:5:A:C_2", "chr1:5:A:C_3"]);
+        for (id, pos) in [("chr1:5:A:C", 5), ("chr1:5:A:C_2", 5), ("chr1:5:A:C_3", 20)] {
+            assert_eq!(source.get(id).unwrap().unwrap().pos, pos);
+        }
+        let region: Region = "chr1:10-30".parse().unwrap();
+        let variants = source.fetch(&region).unwrap();
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].id, "chr1:5:A:C_3");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stale_id_index() {
+        let dir = std::env::temp_dir().join(format!("fm3-gfa-stale-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let vcf_path = dir.join("t.vcf.gz");
+        let vcf_path = vcf_path.to_str().unwrap();
+        let index_path = format!("{}.fm3v", vcf_path);
+
+        // stored bgzf blocks, so records of the same length give a file of the same size
+        let write_vcf = |last_id: &[u8]| {
+            let mut writer = bgzf::writer::Builder::default()
+                .set_compression_level(bgzf::writer::CompressionLevel::NONE)
+                .build_from_writer(File::create(vcf_path).unwrap());
+            writer
+                .write_all(
+                    b"##fileformat=VCFv4.2\n\
+##INFO=<ID=AT,Number=R,Type=String,Description=\"Allele Traversal\">\n\
+##contig=<ID=chr1>\n\
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+chr1\t5\tv1\tA\tC\t.\t.\tAT=>1>2>5,>1>3>5\n",
+                )
+                .unwrap();
+            writer.write_all(b"chr1\t90\t").unwrap();
+            writer.write_all(last_id).unwrap();
+            writer
+                .write_all(b"\tA\tC\t.\t.\tAT=>5>6>8,>5>7>8\n")
+                .unwrap();
+            writer.finish().unwrap();
+            let index = vcf::index(vcf_path).unwrap();
+            tabix::fs::write(format!("{}.tbi", vcf_path), &index).unwrap();
+        };
+        let ids = || {
+            let source =
+                VariantSource::open(vcf_path, None, IdScheme::default(), &IdIndexPath::Beside)
+                    .unwrap();
+            let mut ids = source.ids().unwrap();
+            ids.sort();
+            ids
+        };
+
+        write_vcf(b"v2");
+        assert_eq!(ids(), vec!["v1", "v2"]);
+        // an unchanged VCF reuses the index without writing it again
+        let written = fs::metadata(&index_path).unwrap().modified().unwrap();
+        assert_eq!(ids(), vec!["v1", "v2"]);
+        assert_eq!(
+            fs::metadata(&index_path).unwrap().modified().unwrap(),
+            written
+        );
+
+        // another ID of the same length, with the size and mtime of the old file
+        let size = fs::metadata(vcf_path).unwrap().len();
+        let mtime = fs::metadata(vcf_path).unwrap().modified().unwrap();
+        write_vcf(b"v9");
+        assert_eq!(fs::metadata(vcf_path).unwrap().len(), size);
+        File::options()
+            .write(true)
+            .open(vcf_path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert_eq!(ids(), vec!["v1", "v9"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,44 +1,321 @@
 use anyhow::Result;
 use nom::{
-    branch::alt,
-    character::complete::{char, digit1},
-    multi::many0,
-    sequence::preceded,
-    IResult,
+    branch::alt, bytes::complete::take_while1, character::complete::char, combinator::map,
+    multi::many1, sequence::pair, IResult,
 };
 use noodles::vcf::{
     self,
-    variant::record::{
-        info::field::value::{self, Array},
-        Ids,
+    variant::{
+        record::{
+            info::field::value::{self, Array},
+            samples::{series::Value, Series},
+            AlternateBases, Filters, Ids,
+        },
+        Record as _,
     },
     Header,
 };
+use serde::{Deserialize, Serialize};
 // use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::str::FromStr;
+use std::sync::Arc;
 
-#[derive(Clone, Debug)]
+use crate::gfa::Orientation;
+use crate::path::WalkStep;
+use crate::region::Region;
+
+// a VCF site and its bubble, serialized without traversals and genotypes
+#[derive(Clone, Debug, Serialize)]
 pub struct BubbleVariant {
     pub id: String,
+    pub chrom: String,
+    // 1-based, like the VCF
     pub pos: usize,
+    // 1-based inclusive, from INFO/END or the REF length
+    pub end: usize,
+    #[serde(rename = "ref")]
+    pub ref_allele: String,
+    #[serde(rename = "alt")]
+    pub alt_alleles: Vec<String>,
+    pub qual: Option<f32>,
+    // empty for a missing FILTER
+    pub filter: Vec<String>,
+    // LV, nesting level of the snarl, 0 for a top level bubble
+    pub level: Option<usize>,
+    // PS, ID of the parent snarl
+    pub parent: Option<String>,
+    #[serde(skip)]
     pub allele_traversal: Vec<Traversal>,
+    // GT of the selected samples, empty if the VCF has no sample columns
+    #[serde(skip)]
+    pub genotypes: GenotypeTable,
+}
+
+// sample columns of the VCF kept in the genotype tables
+#[derive(Clone, Debug)]
+pub struct SampleSet {
+    names: Arc<[String]>,
+    // one flag per sample column of the header
+    keep: Vec<bool>,
+}
+
+impl SampleSet {
+    // all samples of the header, or only the ones listed in subset
+    pub fn new(header: &Header, subset: Option<&[String]>) -> Result<Self> {
+        let header_names = header.sample_names();
+        let Some(subset) = subset else {
+            return Ok(Self {
+                names: header_names.iter().cloned().collect(),
+                keep: vec![true; header_names.len()],
+            });
+        };
+        let subset: HashSet<&str> = subset.iter().map(|s| s.as_str()).collect();
+        if let Some(missing) = subset.iter().find(|&&s| !header_names.contains(s)) {
+            return Err(anyhow::anyhow!("Sample not found in VCF: {}", missing));
+        }
+        Ok(Self {
+            names: header_names
+                .iter()
+                .filter(|name| subset.contains(name.as_str()))
+                .cloned()
+                .collect(),
+            keep: header_names
+                .iter()
+                .map(|name| subset.contains(name.as_str()))
+                .collect(),
+        })
+    }
+}
+
+// how records without an ID are named
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum IdScheme {
+    // CHROM:POS:REF:ALT, or CHROM:POS:<hash> when REF and ALT are longer than 32 bp
+    #[default]
+    Auto,
+    // always CHROM:POS:REF:ALT
+    Site,
+    // always CHROM:POS:<crc32 of REF and ALT>
+    Hash,
+}
+
+// alleles longer than this are hashed by IdScheme::Auto
+const MAX_ID_ALLELES_LEN: usize = 32;
+
+// IDs of the records of one pass over a VCF, an ID already handed out, explicit or
+// synthesised, gets the first free _2, _3 ... suffix
+#[derive(Debug, Clone, Default)]
+pub struct RecordIds {
+    scheme: IdScheme,
+    // every ID handed out so far
+    seen: HashSet<String>,
+    // last suffix given to an ID
+    suffixes: HashMap<String, usize>,
+    // IDs a pass over the whole file gave to the records that got a suffix, and to the other
+    // records sharing their ID, CHROM and POS, in file order
+    given: Option<HashMap<(String, String, usize), VecDeque<String>>>,
+}
+
+impl RecordIds {
+    pub fn new(scheme: IdScheme) -> Self {
+        Self {
+            scheme,
+            ..Default::default()
+        }
+    }
+
+    // IDs for a part of the file only, e.g. a region, the records get the IDs in given
+    // instead of being suffixed by what the part holds
+    pub fn with_given(
+        scheme: IdScheme,
+        given: HashMap<(String, String, usize), VecDeque<String>>,
+    ) -> Self {
+        Self {
+            scheme,
+            given: Some(given),
+            ..Default::default()
+        }
+    }
+
+    // the first ID of the record, or one made from its site, unique among the records
+    // of the pass
+    pub fn next(&mut self, record: &vcf::Record) -> Result<String> {
+        let (id, pos) = self.record_id(record)?;
+        if let Some(given) = self.given.as_mut() {
+            let key = (id, record.reference_sequence_name().to_string(), pos);
+            return Ok(match given.get_mut(&key).and_then(|ids| ids.pop_front()) {
+                Some(id) => id,
+                None => key.0,
+            });
+        }
+
+        // duplicate records share the site, and are numbered in file order,
+        // skipping suffixed IDs that are already taken
+        if self.seen.insert(id.clone()) {
+            return Ok(id);
+        }
+        let suffix = self.suffixes.entry(id.clone()).or_insert(1);
+        loop {
+            *suffix += 1;
+            let suffixed = format!("{}_{}", id, suffix);
+            if self.seen.insert(suffixed.clone()) {
+                return Ok(suffixed);
+            }
+        }
+    }
+
+    // the first ID of the record or one made from its site, before any suffix, and the
+    // position of the record
+    pub fn record_id(&self, record: &vcf::Record) -> Result<(String, usize)> {
+        let pos = record
+            .variant_start()
+            .transpose()?
+            .ok_or_else(|| anyhow::anyhow!("Variant position is necessary"))?
+            .get();
+        if let Some(id) = record.ids().iter().next() {
+            return Ok((id.to_string(), pos));
+        }
+        let alts = record
+            .alternate_bases()
+            .iter()
+            .collect::<std::io::Result<Vec<_>>>()?
+            .join(",");
+        let alleles = format!("{}:{}", record.reference_bases(), alts);
+        let hashed = match self.scheme {
+            IdScheme::Auto => alleles.len() > MAX_ID_ALLELES_LEN + 1,
+            IdScheme::Site => false,
+            IdScheme::Hash => true,
+        };
+        let id = if hashed {
+            let hash = crc32fast::hash(alleles.as_bytes());
+            format!("{}:{}:{:08x}", record.reference_sequence_name(), pos, hash)
+        } else {
+            format!("{}:{}:{}", record.reference_sequence_name(), pos, alleles)
+        };
+        Ok((id, pos))
+    }
+}
+
+// genotype calls of one record, the sample names are shared by all records
+#[derive(Clone, Debug, Default)]
+pub struct GenotypeTable {
+    pub samples: Arc<[String]>,
+    // allele index of every haplotype of every sample, None for a missing call
+    pub calls: Vec<Vec<Option<usize>>>,
+}
+
+// count, frequency and carriers of one allele among the called haplotypes
+#[derive(Debug, Clone, Serialize)]
+pub struct AlleleGenotypes {
+    pub allele: usize,
+    pub name: String,
+    pub count: usize,
+    pub frequency: f64,
+    pub carriers: Vec<String>,
+}
+
+impl GenotypeTable {
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    // GT of the kept samples of a record
+    fn from_vcf_record(record: &vcf::Record, header: &Header, samples: &SampleSet) -> Result<Self> {
+        let mut calls = Vec::with_capacity(samples.names.len());
+        let record_samples = record.samples();
+        let Some(series) = record_samples.select("GT") else {
+            return Ok(Self::default());
+        };
+        for (value, &keep) in series.iter(header).zip(samples.keep.iter()) {
+            if !keep {
+                continue;
+            }
+            let call = match value? {
+                Some(Value::Genotype(genotype)) => genotype
+                    .iter()
+                    .map(|allele| allele.map(|(position, _)| position))
+                    .collect::<std::io::Result<Vec<_>>>()?,
+                Some(_) => Err(anyhow::anyhow!("GT should be a genotype"))?,
+                None => Vec::new(),
+            };
+            calls.push(call);
+        }
+        Ok(Self {
+            samples: samples.names.clone(),
+            calls,
+        })
+    }
+
+    // summary of every allele, allele_count includes REF
+    pub fn allele_stats(&self, allele_count: usize) -> Vec<AlleleGenotypes> {
+        let called = self.calls.iter().flatten().filter(|a| a.is_some()).count();
+        (0..allele_count)
+            .map(|allele| {
+                let mut count = 0;
+                let mut carriers = Vec::new();
+                for (sample, call) in self.samples.iter().zip(self.calls.iter()) {
+                    let copies = call.iter().filter(|&&a| a == Some(allele)).count();
+                    if copies > 0 {
+                        count += copies;
+                        carriers.push(sample.clone());
+                    }
+                }
+                AlleleGenotypes {
+                    allele,
+                    name: BubbleVariant::allele_name(allele),
+                    count,
+                    frequency: if called > 0 {
+                        count as f64 / called as f64
+                    } else {
+                        0.0
+                    },
+                    carriers,
+                }
+            })
+            .collect()
+    }
+}
+
+// sample names from a file, one per line
+pub fn read_sample_list(path: &str) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
 }
 
 type node = Vec<u8>;
 
 #[derive(Debug, Clone)]
 pub struct Traversal {
-    pub nodes: Vec<node>, // store node indices
+    // segment id and strand of every step, > is forward and < is reverse
+    pub steps: Vec<WalkStep>,
 }
 
 pub fn parse_vcf_file(path: &str) -> Result<Vec<BubbleVariant>> {
+    parse_vcf_file_with(path, None, IdScheme::default())
+}
+
+// keep the genotypes of a subset of samples only, records without ID are named by scheme
+pub fn parse_vcf_file_with(
+    path: &str,
+    samples: Option<&[String]>,
+    scheme: IdScheme,
+) -> Result<Vec<BubbleVariant>> {
     let mut variants = Vec::new();
     let mut reader = vcf::io::reader::Builder::default().build_from_path(path)?;
     let header = reader.read_header()?;
+    let samples = SampleSet::new(&header, samples)?;
+    let mut ids = RecordIds::new(scheme);
 
     for result in reader.records() {
         let record = result?;
-        let variant = BubbleVariant::from_vcf_record(&record, &header)?;
+        let variant = BubbleVariant::from_vcf_record_with(&record, &header, &samples, &mut ids)?;
         variants.push(variant);
     }
 
@@ -50,29 +327,91 @@ impl BubbleVariant {
     pub fn new(id: String, pos: usize, allele_traversal: Vec<Traversal>) -> Result<Self> {
         Ok(Self {
             id,
+            chrom: String::new(),
             pos,
+            end: pos,
+            ref_allele: String::new(),
+            alt_alleles: Vec::new(),
+            qual: None,
+            filter: Vec::new(),
+            level: None,
+            parent: None,
             allele_traversal,
+            genotypes: GenotypeTable::default(),
         })
     }
 
     // create BubbleVariant from VCF record
     pub fn from_vcf_record(record: &vcf::Record, header: &Header) -> Result<Self> {
-        let id = Self::get_id(record)?;
+        Self::from_vcf_record_with(
+            record,
+            header,
+            &SampleSet::new(header, None)?,
+            &mut RecordIds::default(),
+        )
+    }
+
+    // create BubbleVariant from VCF record, with the GT of the given samples
+    pub fn from_vcf_record_with(
+        record: &vcf::Record,
+        header: &Header,
+        samples: &SampleSet,
+        ids: &mut RecordIds,
+    ) -> Result<Self> {
+        let id = ids.next(record)?;
 
         let pos = record.variant_start().transpose()?.unwrap().get();
 
         let allele_traversals = Self::get_allele_traversal(record, header)?;
 
-        Self::new(id, pos, allele_traversals)
+        let mut variant = Self::new(id, pos, allele_traversals)?;
+        variant.chrom = record.reference_sequence_name().to_string();
+        variant.end = record.variant_end(header)?.get();
+        variant.ref_allele = record.reference_bases().to_string();
+        variant.alt_alleles = record
+            .alternate_bases()
+            .iter()
+            .map(|alt| alt.map(|alt| alt.to_string()))
+            .collect::<std::io::Result<_>>()?;
+        variant.qual = record.quality_score().transpose()?;
+        variant.filter = record
+            .filters()
+            .iter(header)
+            .map(|filter| filter.map(|filter| filter.to_string()))
+            .collect::<std::io::Result<_>>()?;
+        (variant.level, variant.parent) = Self::get_nesting(record, header)?;
+        variant.genotypes = GenotypeTable::from_vcf_record(record, header, samples)?;
+        Ok(variant)
     }
 
-    // aux function to get variant ID
-    fn get_id(record: &vcf::Record) -> Result<String> {
-        let id = match record.ids().iter().next() {
-            Some(ids) => ids.to_string(),
-            None => Err(anyhow::anyhow!("Variant ID is necessary"))?,
+    // aux func to get LV and PS written by vg deconstruct
+    pub(crate) fn get_nesting(
+        record: &vcf::Record,
+        header: &Header,
+    ) -> Result<(Option<usize>, Option<String>)> {
+        let info = record.info();
+        let level = match info.get(header, "LV").transpose()?.flatten() {
+            Some(value::Value::Integer(level)) => Some(level.max(0) as usize),
+            Some(value::Value::String(level)) => level.parse().ok(),
+            _ => None,
         };
-        Ok(id)
+        let parent = match info.get(header, "PS").transpose()?.flatten() {
+            Some(value::Value::String(parent)) => Some(parent.to_string()),
+            _ => None,
+        };
+        Ok((level, parent))
+    }
+
+    // REF followed by the ALT alleles
+    pub fn alleles(&self) -> Vec<String> {
+        let mut alleles = vec![self.ref_allele.clone()];
+        alleles.extend(self.alt_alleles.iter().cloned());
+        alleles
+    }
+
+    // whether the site [pos, end] overlaps a region
+    pub fn overlaps(&self, region: &Region) -> bool {
+        region.overlaps(&self.chrom, self.pos - 1, self.end.max(self.pos))
     }
 
     // aux func to get allele traversal
@@ -116,51 +455,88 @@ impl BubbleVariant {
         Ok(allele_traversals)
     }
 
-    // get ref nodes from allele traversal
-    pub fn get_ref_nodes(&self, tail: bool) -> Vec<node> {
-        // first AT is ref nodes
-        // strip first node and last node if long is false
-        if tail {
-            self.allele_traversal[0].nodes.to_vec()
-        } else {
-            self.allele_traversal[0].nodes[1..self.allele_traversal[0].nodes.len() - 1].to_vec()
+    // status of the nodes of an allele: REF for the first AT, then ALT1, ALT2 ...
+    pub fn allele_name(allele: usize) -> String {
+        match allele {
+            0 => "REF".to_string(),
+            n => format!("ALT{}", n),
         }
     }
 
-    // get alt nodes from allele traversal
-    pub fn get_alt_nodes(&self, tail: bool) -> Vec<node> {
-        // skip first AT
-        // strip first node and last node
+    // number of alleles, REF included
+    pub fn allele_count(&self) -> usize {
+        self.allele_traversal.len()
+    }
+
+    // get oriented steps of one allele traversal
+    pub fn get_allele_steps(&self, allele: usize, tail: bool) -> Vec<WalkStep> {
+        let Some(traversal) = self.allele_traversal.get(allele) else {
+            return Vec::new();
+        };
+        let steps = &traversal.steps;
+        // strip first node and last node if tail is false
         if tail {
-            self.allele_traversal[1].nodes.to_vec()
+            steps.to_vec()
+        } else if steps.len() > 2 {
+            steps[1..steps.len() - 1].to_vec()
         } else {
-            self.allele_traversal[1].nodes[1..self.allele_traversal[1].nodes.len() - 1].to_vec()
+            Vec::new()
         }
     }
+
+    // get nodes of one allele traversal
+    pub fn get_allele_nodes(&self, allele: usize, tail: bool) -> Vec<node> {
+        self.get_allele_steps(allele, tail)
+            .into_iter()
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    // get ref nodes from allele traversal, the first AT is the ref
+    pub fn get_ref_nodes(&self, tail: bool) -> Vec<node> {
+        self.get_allele_nodes(0, tail)
+    }
 }
 
 impl Traversal {
-    // nom parser separated by > or <
-    fn parse_separator(input: &str) -> IResult<&str, char> {
-        alt((char('>'), char('<')))(input)
+    // nom parser for the strand of a step, > or <
+    fn parse_orientation(input: &str) -> IResult<&str, Orientation> {
+        alt((
+            map(char('>'), |_| Orientation::Forward),
+            map(char('<'), |_| Orientation::Backward),
+        ))(input)
+    }
+
+    // nom parser for a segment name, any printable characters but the step and INFO separators
+    fn parse_segment(input: &str) -> IResult<&str, &str> {
+        take_while1(|c: char| c.is_ascii_graphic() && !matches!(c, '>' | '<' | ',' | ';' | '='))(
+            input,
+        )
     }
 
-    // nom parser for single node
-    fn parse_single_node(input: &str) -> IResult<&str, Vec<u8>> {
-        let (input, num) = preceded(Self::parse_separator, digit1)(input)?;
-        // to byte
-        Ok((input, num.as_bytes().to_vec()))
+    // nom parser for single step
+    fn parse_step(input: &str) -> IResult<&str, WalkStep> {
+        let (input, (orient, name)) = pair(Self::parse_orientation, Self::parse_segment)(input)?;
+        Ok((input, (name.as_bytes().to_vec(), orient)))
     }
 
     // nom parser for Traversal
     fn parse(input: &str) -> IResult<&str, Self> {
-        let (input, first) = Self::parse_single_node(input)?;
-        let (input, rest) = many0(Self::parse_single_node)(input)?;
+        let (input, steps) = many1(Self::parse_step)(input)?;
+        Ok((input, Traversal { steps }))
+    }
+
+    // segment ids of the steps
+    pub fn nodes(&self) -> impl Iterator<Item = &node> + '_ {
+        self.steps.iter().map(|(node, _)| node)
+    }
 
-        let mut nodes = vec![first];
-        nodes.extend(rest);
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
 
-        Ok((input, Traversal { nodes }))
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
     }
 
     // Heavy Regex parser, it's slower than nom, just for comparison
@@ -188,10 +564,22 @@ impl FromStr for Traversal {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // point at the first character that is not a > or < step
+        let malformed = |at: &str| {
+            format!(
+                "Malformed allele traversal {:?}: expected >segment or <segment at column {}",
+                s,
+                s.len() - at.len() + 1
+            )
+        };
+        if s.is_empty() {
+            return Err("Empty allele traversal".to_string());
+        }
         match Self::parse(s) {
             Ok(("", traversal)) => Ok(traversal),
-            Ok((remainder, _)) => Err(format!("Unparsed input remaining: {}", remainder)),
-            Err(e) => Err(format!("Parse error: {}", e)),
+            Ok((remainder, _)) => Err(malformed(remainder)),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(malformed(e.input)),
+            Err(nom::Err::Incomplete(_)) => Err(malformed("")),
         }
         // Self::parse_regex(s)
     }
@@ -206,11 +594,8 @@ mod tests {
         let input = ">21610>21611>21612>21613>21614";
         let traversal = Traversal::from_str(input).unwrap();
 
-        assert_eq!(traversal.nodes[0], b"21610");
-        assert_eq!(traversal.nodes[1], b"21611");
-        assert_eq!(traversal.nodes[2], b"21612");
-        assert_eq!(traversal.nodes[3], b"21613");
-        assert_eq!(traversal.nodes[4], b"21614");
+        let nodes: Vec<_> = traversal.nodes().collect();
+        assert_eq!(nodes, [b"21610", b"21611", b"21612", b"21613", b"21614"]);
     }
 
     #[test]
@@ -218,8 +603,133 @@ mod tests {
         let input = ">21610<21611>21612";
         let traversal = Traversal::from_str(input).unwrap();
 
-        assert_eq!(traversal.nodes[0], b"21610");
-        assert_eq!(traversal.nodes[1], b"21611");
-        assert_eq!(traversal.nodes[2], b"21612");
+        assert_eq!(
+            traversal.steps[0],
+            (b"21610".to_vec(), Orientation::Forward)
+        );
+        assert_eq!(
+            traversal.steps[1],
+            (b"21611".to_vec(), Orientation::Backward)
+        );
+        assert_eq!(
+            traversal.steps[2],
+            (b"21612".to_vec(), Orientation::Forward)
+        );
+    }
+
+    #[test]
+    fn test_segment_names() {
+        let input = ">21610<s21611>chr1_5000";
+        let traversal = Traversal::from_str(input).unwrap();
+
+        assert_eq!(
+            traversal.steps,
+            vec![
+                (b"21610".to_vec(), Orientation::Forward),
+                (b"s21611".to_vec(), Orientation::Backward),
+                (b"chr1_5000".to_vec(), Orientation::Forward),
+            ]
+        );
+        for malformed in ["", "21610>21611", ">21610>>21611", ">1 >2"] {
+            assert!(Traversal::from_str(malformed).is_err(), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn test_multi_allelic_nodes() {
+        let traversals = [">1>2>5", ">1>3>5", ">1>4>4>5", ">1>5"]
+            .iter()
+            .map(|at| Traversal::from_str(at).unwrap())
+            .collect();
+        let bubble = BubbleVariant::new("v1".to_string(), 1, traversals).unwrap();
+
+        assert_eq!(bubble.allele_count(), 4);
+        assert_eq!(BubbleVariant::allele_name(0), "REF");
+        assert_eq!(BubbleVariant::allele_name(2), "ALT2");
+        assert_eq!(bubble.get_allele_nodes(2, false), vec![b"4".to_vec(); 2]);
+        assert!(bubble.get_allele_nodes(3, false).is_empty());
+        assert!(bubble.get_allele_nodes(4, true).is_empty());
+    }
+
+    #[test]
+    fn test_genotype_table() {
+        let data = b"##fileformat=VCFv4.2\n\
+##INFO=<ID=AT,Number=R,Type=String,Description=\"Allele Traversal\">\n\
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3\n\
+chr1\t5\tv1\tA\tC,G\t.\t.\tAT=>1>2>5,>1>3>5,>1>4>5\tGT\t0|1\t2/2\t.|1\n";
+        let mut reader = vcf::io::Reader::new(&data[..]);
+        let header = reader.read_header().unwrap();
+        let record = reader.records().next().unwrap().unwrap();
+
+        let all = SampleSet::new(&header, None).unwrap();
+        let bubble =
+            BubbleVariant::from_vcf_record_with(&record, &header, &all, &mut RecordIds::default())
+                .unwrap();
+        let stats = bubble.genotypes.allele_stats(bubble.allele_count());
+        assert_eq!(bubble.genotypes.calls[2], vec![None, Some(1)]);
+        assert_eq!(
+            stats.iter().map(|s| s.count).collect::<Vec<_>>(),
+            vec![1, 2, 2]
+        );
+        assert_eq!(stats[1].carriers, vec!["S1", "S3"]);
+        assert_eq!(stats[2].frequency, 0.4);
+
+        let subset = SampleSet::new(&header, Some(&["S2".to_string()])).unwrap();
+        let bubble = BubbleVariant::from_vcf_record_with(
+            &record,
+            &header,
+            &subset,
+            &mut RecordIds::default(),
+        )
+        .unwrap();
+        assert_eq!(bubble.genotypes.samples.as_ref(), ["S2".to_string()]);
+        assert_eq!(bubble.genotypes.allele_stats(3)[2].frequency, 1.0);
+        assert!(SampleSet::new(&header, Some(&["S9".to_string()])).is_err());
+    }
+
+    #[test]
+    fn test_record_ids() {
+        let data = b"##fileformat=VCFv4.2\n\
+##INFO=<ID=AT,Number=R,Type=String,Description=\"Allele Traversal\">\n\
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+chr1\t5\t.\tA\tC,G\t.\t.\tAT=>1>2>5,>1>3>5,>1>4>5\n\
+chr1\t5\t.\tA\tC,G\t.\t.\tAT=>1>2>5,>1>3>5,>1>4>5\n\
+chr1\t9\tv3\tA\tC\t.\t.\tAT=>5>6>8,>5>7>8\n\
+chr1\t9\tchr1:9:A:C_2\tA\tC\t.\t.\tAT=>5>6>8,>5>7>8\n\
+chr1\t9\t.\tA\tC\t.\t.\tAT=>5>6>8,>5>7>8\n\
+chr1\t9\t.\tA\tC\t.\t.\tAT=>5>6>8,>5>7>8\n\
+chr1\t12\tchr1:5:A:C,G\tA\tC\t.\t.\tAT=>8>9>11,>8>10>11\n";
+        let mut reader = vcf::io::Reader::new(&data[..]);
+        let header = reader.read_header().unwrap();
+        let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+
+        let mut ids = RecordIds::new(IdScheme::Auto);
+        let names: Vec<_> = records.iter().map(|r| ids.next(r).unwrap()).collect();
+        // the explicit chr1:9:A:C_2 is skipped by the synthesised duplicates, and the
+        // explicit chr1:5:A:C,G after them is suffixed as well
+        assert_eq!(
+            names,
+            vec![
+                "chr1:5:A:C,G",
+                "chr1:5:A:C,G_2",
+                "v3",
+                "chr1:9:A:C_2",
+                "chr1:9:A:C",
+                "chr1:9:A:C_3",
+                "chr1:5:A:C,G_3"
+            ]
+        );
+
+        let mut ids = RecordIds::new(IdScheme::Hash);
+        let bubble = BubbleVariant::from_vcf_record_with(
+            &records[0],
+            &header,
+            &SampleSet::new(&header, None).unwrap(),
+            &mut ids,
+        )
+        .unwrap();
+        let hash = format!("{:08x}", crc32fast::hash(b"A:C,G"));
+        assert_eq!(bubble.id, format!("chr1:5:{}", hash));
     }
 }